@@ -0,0 +1,43 @@
+; Spectrum standard library
+;
+; link it with the programs using it, e.g. spectrum_vm build main.asm lib/std.asm
+; and declare the routines they call with .extern
+; routines follow the calling convention: arguments in $0-$3, result in $0,
+; $4-$15 may be overwritten
+.global max, min, abs, strlen
+
+; $0 = the larger of $0 and $1
+max:
+    CMP $0 $1
+    CMOVLT $0 $1
+    RET
+
+; $0 = the smaller of $0 and $1
+min:
+    CMP $0 $1
+    CMOVGT $0 $1
+    RET
+
+; $0 = absolute value of $0
+abs:
+    LOAD $5 #0
+    SUB $5 $0 $4
+    CMP $0 $5
+    CMOVLT $0 $4
+    RET
+
+; $0 = length of the zero terminated string at data address $0
+strlen:
+    LOAD $4 #0
+    LOAD $6 #0
+    LA $7 @strlen_loop
+strlen_loop:
+    LOADDB $5 $0
+    CMP $5 $6
+    BZ @strlen_done
+    INC $0
+    INC $4
+    JMP $7
+strlen_done:
+    ADD $4 $6 $0
+    RET
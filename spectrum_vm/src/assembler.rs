@@ -1,3 +1,81 @@
+pub mod diagnostics;
+pub mod include;
 pub mod lexer;
+pub mod linker;
+pub mod macros;
 pub mod parser;
 pub mod program;
+pub mod symbols;
+
+use std::path::Path;
+
+use self::{
+    diagnostics::Diagnostics,
+    include::Includes,
+    lexer::{Lexer, Token},
+    macros::MacroExpander,
+    parser::{ParseError, Parser},
+    program::Program,
+    symbols::SymbolTable,
+};
+
+/// parse a whole source file into a program, checking its labels and sections
+/// `.include` paths are relative to the working directory
+pub fn assemble(source: &str) -> Result<Program, Diagnostics> {
+    assemble_in(source, Path::new("."))
+}
+
+/// `assemble` with `.include` paths relative to `directory`
+/// includes and macros are expanded before parsing, every error is reported
+/// and the program is only checked once it parses
+pub fn assemble_in(source: &str, directory: &Path) -> Result<Program, Diagnostics> {
+    assemble_into(Program::default(), source, directory)
+}
+
+/// `assemble` for code and data placed at `code_origin` and `data_origin`, after those
+/// already loaded in the vm whose `labels` the source can reference, as the repl does
+/// for every line
+pub fn assemble_at(
+    source: &str,
+    code_origin: usize,
+    data_origin: usize,
+    labels: &SymbolTable,
+) -> Result<Program, Diagnostics> {
+    assemble_into(
+        Program::at(code_origin, data_origin, labels.clone()),
+        source,
+        Path::new("."),
+    )
+}
+
+fn assemble_into(
+    mut program: Program,
+    source: &str,
+    directory: &Path,
+) -> Result<Program, Diagnostics> {
+    let mut lexer: Lexer = Lexer::new(source, source.len());
+    lexer.tokenize();
+    let mut includes: Includes = Includes::new(directory);
+    let tokens: Vec<Token> = includes.resolve(lexer.tokens);
+    let mut expander: MacroExpander = MacroExpander::new();
+    let tokens: Vec<Token> = expander.expand(tokens);
+    let mut errors: Vec<ParseError> = lexer.errors;
+    errors.append(&mut includes.errors);
+    errors.append(&mut expander.errors);
+    let mut parser: Parser = Parser::new(tokens);
+    match parser.parse() {
+        Ok(instructions) if errors.is_empty() => program.set_instructions(instructions),
+        Ok(_) => {}
+        Err(parse_errors) => errors.extend(parse_errors),
+    }
+    if errors.is_empty() {
+        errors = program.errors();
+    }
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        let mut diagnostics: Diagnostics = Diagnostics::new(errors);
+        diagnostics.includes = includes.files;
+        Err(diagnostics)
+    }
+}
@@ -0,0 +1,190 @@
+use std::fmt;
+
+use super::{
+    include::SourceFile,
+    lexer::{Token, TokenKind},
+    parser::ParseError,
+};
+
+/// every error found while assembling a source, in source order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostics {
+    /// name of the source in reports, if it came from a file
+    pub file: Option<String>,
+    /// files included by the source, that errors can point into
+    pub includes: Vec<SourceFile>,
+    pub errors: Vec<ParseError>,
+}
+
+impl Diagnostics {
+    pub fn new(mut errors: Vec<ParseError>) -> Self {
+        // errors inside macro expansions are ordered by their invocation,
+        // those of included files come after the ones of the source
+        errors.sort_by_key(|err| {
+            let origin: &Token = origin(&err.token);
+            (origin.file(), origin.start(), err.token.start())
+        });
+        Self {
+            file: None,
+            includes: Vec::new(),
+            errors,
+        }
+    }
+
+    /// rustc like report of every error, with the offending source line
+    /// and a caret under the token span, followed by the macro invocations
+    /// that expanded it
+    pub fn render(&self, source: &str) -> String {
+        let mut report: String = String::new();
+        for err in &self.errors {
+            report.push_str(&format!("error: {}\n", err.message));
+            report.push_str(&self.snippet(&err.token, source));
+            let mut expansion: Option<&Token> = err.token.expansion();
+            while let Some(invocation) = expansion {
+                if let TokenKind::Identifier { name } = &invocation.token_kind {
+                    report.push_str(&format!("note: in expansion of macro '{}'\n", name));
+                }
+                report.push_str(&self.snippet(invocation, source));
+                expansion = invocation.expansion();
+            }
+        }
+        report
+    }
+
+    /// location of `token`, its source line and a caret under its span
+    fn snippet(&self, token: &Token, source: &str) -> String {
+        let source: &str = match token.file().checked_sub(1) {
+            Some(index) => &self.includes[index].content,
+            None => source,
+        };
+        let start: usize = usize::min(token.start(), source.len());
+        let line_start: usize = source[..start].rfind('\n').map_or(0, |index| index + 1);
+        let line_end: usize = source[start..]
+            .find('\n')
+            .map_or(source.len(), |index| start + index);
+        let line: &str = source[line_start..line_end].trim_end_matches('\r');
+        let indent: usize = source[line_start..start].chars().count();
+        let span_end: usize = usize::min(usize::max(token.end(), start), line_start + line.len());
+        let width: usize = usize::max(source[start..span_end].chars().count(), 1);
+        let gutter: String = " ".repeat(token.line().to_string().len());
+
+        let mut snippet: String = String::new();
+        snippet.push_str(&format!("{}--> {}\n", gutter, self.location(token)));
+        snippet.push_str(&format!("{} |\n", gutter));
+        snippet.push_str(&format!("{} | {}\n", token.line(), line));
+        snippet.push_str(&format!(
+            "{} | {}{}\n",
+            gutter,
+            " ".repeat(indent),
+            "^".repeat(width)
+        ));
+        snippet
+    }
+
+    fn location(&self, token: &Token) -> String {
+        let file: &str = match token.file().checked_sub(1) {
+            Some(index) => &self.includes[index].name,
+            None => self.file.as_deref().unwrap_or("<source>"),
+        };
+        format!("{}:{}:{}", file, token.line(), token.column())
+    }
+}
+
+/// outermost macro invocation that expanded `token`, or the token itself
+fn origin(token: &Token) -> &Token {
+    match token.expansion() {
+        Some(invocation) => origin(invocation),
+        None => token,
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, err) in self.errors.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}: {}", self.location(&err.token), err.message)?;
+            let mut expansion: Option<&Token> = err.token.expansion();
+            while let Some(invocation) = expansion {
+                write!(f, ", in macro expanded at {}", self.location(invocation))?;
+                expansion = invocation.expansion();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+#[cfg(test)]
+mod test {
+    use crate::assembler::assemble;
+
+    use super::*;
+
+    #[test]
+    fn render_errors() {
+        let source: &str = "LOAD $1 #5\nADD $0 $x $2\nHLT\n";
+        let mut diagnostics: Diagnostics = assemble(source).unwrap_err();
+        diagnostics.file = Some("prog.asm".into());
+        assert_eq!(diagnostics.errors.len(), 1);
+        assert_eq!(
+            diagnostics.render(source),
+            "error: failed to tokenize register index\n \
+             --> prog.asm:2:8\n  \
+             |\n\
+             2 | ADD $0 $x $2\n  \
+             |        ^^\n"
+        );
+        assert_eq!(
+            diagnostics.to_string(),
+            "prog.asm:2:8: failed to tokenize register index"
+        )
+    }
+
+    #[test]
+    fn report_every_error() {
+        let source: &str = "LOAD $1 #5\nHLT $1\nJMP @nowhere\n.data\nINC $0\n";
+        let diagnostics: Diagnostics = assemble(source).unwrap_err();
+        let lines: Vec<usize> = diagnostics
+            .errors
+            .iter()
+            .map(|err| err.token.line())
+            .collect();
+        assert_eq!(lines, [2, 3]);
+        let source: &str = "LA $1 @nowhere\ntwice: HLT\ntwice: HLT\n.data\nINC $0\n";
+        let diagnostics: Diagnostics = assemble(source).unwrap_err();
+        let lines: Vec<usize> = diagnostics
+            .errors
+            .iter()
+            .map(|err| err.token.line())
+            .collect();
+        assert_eq!(lines, [1, 3, 5])
+    }
+
+    #[test]
+    fn render_macro_errors() {
+        let source: &str = ".macro set reg, value\nLOAD \\reg \\value\n.endm\nset #1 #2\n";
+        let mut diagnostics: Diagnostics = assemble(source).unwrap_err();
+        diagnostics.file = Some("prog.asm".into());
+        assert_eq!(
+            diagnostics.render(source),
+            "error: LOAD expects 2 operand(s), operand 1 must be a register\n \
+             --> prog.asm:2:6\n  \
+             |\n\
+             2 | LOAD \\reg \\value\n  \
+             |      ^^^^\n\
+             note: in expansion of macro 'set'\n \
+             --> prog.asm:4:1\n  \
+             |\n\
+             4 | set #1 #2\n  \
+             | ^^^\n"
+        );
+        assert_eq!(
+            diagnostics.to_string(),
+            "prog.asm:2:6: LOAD expects 2 operand(s), operand 1 must be a register, \
+             in macro expanded at prog.asm:4:1"
+        )
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    lexer::{Lexer, Token, TokenKind},
+    parser::ParseError,
+};
+
+/// a source file read by `.include`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceFile {
+    pub name: String,
+    pub content: String,
+}
+
+/// replaces `.include "file.asm"` directives by the tokens of the file, paths are
+/// relative to the directory of the file containing the directive
+pub struct Includes {
+    directory: PathBuf,
+    /// every included file, a token of `files[i]` has `i + 1` as file index
+    pub files: Vec<SourceFile>,
+    pub errors: Vec<ParseError>,
+}
+
+impl Includes {
+    /// `directory` is the one of the assembled source
+    pub fn new(directory: &Path) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+            files: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn resolve(&mut self, tokens: Vec<Token>) -> Vec<Token> {
+        let directory: PathBuf = self.directory.clone();
+        let mut resolved: Vec<Token> = Vec::new();
+        self.resolve_into(tokens, &directory, &mut Vec::new(), &mut resolved);
+        resolved
+    }
+
+    /// `stack` holds the files being included, to detect include cycles
+    fn resolve_into(
+        &mut self,
+        tokens: Vec<Token>,
+        directory: &Path,
+        stack: &mut Vec<PathBuf>,
+        output: &mut Vec<Token>,
+    ) {
+        let mut iterator = tokens.into_iter().peekable();
+        while let Some(token) = iterator.next() {
+            if !matches!(&token.token_kind, TokenKind::Directive { name } if name == "include") {
+                output.push(token);
+                continue;
+            }
+            let path: String = match iterator.next_if(|next| {
+                matches!(next.token_kind, TokenKind::StringOperand { .. })
+                    && next.line() == token.line()
+            }) {
+                Some(Token {
+                    token_kind: TokenKind::StringOperand { value },
+                    ..
+                }) => value,
+                _ => {
+                    self.error(".include expects one file name".into(), &token);
+                    continue;
+                }
+            };
+            let path: PathBuf = directory.join(path);
+            let canonical: PathBuf = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+            if stack.contains(&canonical) {
+                let message: String = format!("{} includes itself", path.display());
+                self.error(message, &token);
+                continue;
+            }
+            let content: String = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(err) => {
+                    let message: String = format!("couldn't read {} : {}", path.display(), err);
+                    self.error(message, &token);
+                    continue;
+                }
+            };
+
+            self.files.push(SourceFile {
+                name: path.display().to_string(),
+                content,
+            });
+            let file: usize = self.files.len();
+            let content: &str = &self.files[file - 1].content;
+            let mut lexer: Lexer = Lexer::new(content, content.len());
+            lexer.set_file(file);
+            lexer.tokenize();
+            self.errors.append(&mut lexer.errors);
+            let mut tokens: Vec<Token> = lexer.tokens;
+            // the included tokens go on with the tokens of the including file
+            tokens.pop();
+
+            stack.push(canonical);
+            let included_directory: &Path = path.parent().unwrap_or(directory);
+            self.resolve_into(tokens, included_directory, stack, output);
+            stack.pop();
+        }
+    }
+
+    fn error(&mut self, message: String, token: &Token) {
+        self.errors.push(ParseError {
+            message,
+            token: token.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use crate::instruction::Opcode;
+
+    use super::*;
+
+    /// temporary directory holding `files`, removed by the caller
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory: PathBuf = env::temp_dir().join(format!("spectrum_include_{}", test));
+        fs::create_dir_all(directory.join("lib")).unwrap();
+        for (name, content) in files {
+            fs::write(directory.join(name), content).unwrap();
+        }
+        directory
+    }
+
+    fn resolve_source(directory: &Path, content: &str) -> (Vec<Token>, Includes) {
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        let mut includes: Includes = Includes::new(directory);
+        let tokens: Vec<Token> = includes.resolve(lexer.tokens);
+        (tokens, includes)
+    }
+
+    #[test]
+    fn include_files() {
+        let directory: PathBuf = write_files(
+            "nested",
+            &[
+                ("lib/io.asm", "INC $1\n.include \"more.asm\""),
+                ("lib/more.asm", "DEC $2"),
+            ],
+        );
+        let (tokens, includes) = resolve_source(&directory, ".include \"lib/io.asm\"\nHLT");
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(includes.errors, []);
+        let kinds: Vec<(TokenKind, usize)> = tokens
+            .iter()
+            .map(|token| (token.token_kind.clone(), token.file()))
+            .collect();
+        assert_eq!(kinds.len(), 6);
+        assert_eq!(kinds[2], (TokenKind::Operation { code: Opcode::DEC }, 2));
+        assert_eq!(kinds[4], (TokenKind::Operation { code: Opcode::HLT }, 0));
+        assert!(includes.files[1].name.ends_with("more.asm"))
+    }
+
+    #[test]
+    fn include_errors() {
+        let directory: PathBuf = write_files("errors", &[("loop.asm", ".include \"loop.asm\"")]);
+        let (_, includes) = resolve_source(
+            &directory,
+            ".include \"loop.asm\"\n.include \"missing.asm\"\n.include",
+        );
+        fs::remove_dir_all(&directory).unwrap();
+        let messages: Vec<&str> = includes
+            .errors
+            .iter()
+            .map(|err| err.message.as_str())
+            .collect();
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].ends_with("loop.asm includes itself"));
+        assert!(messages[1].starts_with("couldn't read"));
+        assert_eq!(messages[2], ".include expects one file name")
+    }
+}
@@ -1,21 +1,78 @@
-use std::str::Chars;
+use std::{num::IntErrorKind, str::Chars};
 
-use crate::instruction::Opcode;
+use crate::{instruction::Opcode, vm::REGISTER_COUNT};
+
+use super::parser::ParseError;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
-    Operation { code: Opcode },
-    Register { reg_index: usize },
-    IntegerOperand { value: i32 },
+    Operation {
+        code: Opcode,
+    },
+    Register {
+        reg_index: usize,
+    },
+    IntegerOperand {
+        value: i32,
+    },
+    /// `%f0` to `%f31`
+    FloatRegister {
+        reg_index: usize,
+    },
+    /// `#3.14`, a decimal literal with a fractional part
+    FloatOperand {
+        value: f64,
+    },
+    LabelDeclaration {
+        name: String,
+    },
+    LabelUsage {
+        name: String,
+    },
+    PseudoOperation {
+        name: String,
+    },
+    Directive {
+        name: String,
+    },
+    StringOperand {
+        value: String,
+    },
+    MemoryOperand {
+        base: usize,
+        offset: i32,
+    },
+    /// bare word that is not a mnemonic, a macro name or parameter
+    Identifier {
+        name: String,
+    },
+    /// `\name` reference to a parameter in a macro body
+    MacroParameter {
+        name: String,
+    },
+    /// text that could not be tokenized, its error is in `Lexer::errors`
+    Invalid,
     Eof,
 }
 
+/// mnemonics that the parser expands into real instructions
+pub const PSEUDO_OPERATIONS: [&str; 2] = ["LA", "LOADF"];
+
+const REGISTER_RANGE_ERROR: &str = "register index out of range (0..=31)";
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub token_kind: TokenKind,
     start: usize,
     end: usize,
     length: usize,
+    line: usize,
+    column: usize,
+    /// index of the source file the token was read from, 0 for the assembled
+    /// source and the position of the file in `Includes::files` plus one otherwise
+    file: usize,
+    /// invocation of the macro this token was expanded from
+    expansion: Option<Box<Token>>,
 }
 
 impl Token {
@@ -25,8 +82,50 @@ impl Token {
             start,
             end,
             length: end - start,
+            line: 0,
+            column: 0,
+            file: 0,
+            expansion: None,
+        }
+    }
+
+    /// copy of a macro body token, expanded by `invocation`
+    pub fn expanded(&self, token_kind: TokenKind, invocation: &Token) -> Self {
+        Self {
+            token_kind,
+            expansion: Some(Box::new(invocation.clone())),
+            ..self.clone()
         }
     }
+
+    /// invocation of the macro the token was expanded from, if any
+    pub fn expansion(&self) -> Option<&Token> {
+        self.expansion.as_deref()
+    }
+
+    /// byte offset of the first character of the token in its source
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// byte offset just past the token in its source
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// 1-based line of the token in its source
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// 1-based column of the token in its source
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn file(&self) -> usize {
+        self.file
+    }
 }
 
 pub struct Lexer<'a> {
@@ -35,7 +134,13 @@ pub struct Lexer<'a> {
     iterator: Chars<'a>,
     line: usize,
     start_of_line: usize,
+    /// source file index given to the tokens
+    file: usize,
     pub tokens: Vec<Token>,
+    /// lexical errors, each pointing at the `Invalid` token it produced
+    pub errors: Vec<ParseError>,
+    /// error of the token being read, attached to it once its span is known
+    pending_error: Option<String>,
 }
 
 impl<'a> Lexer<'a> {
@@ -46,14 +151,15 @@ impl<'a> Lexer<'a> {
             iterator: content.chars(),
             line: 0,
             start_of_line: 0,
+            file: 0,
             tokens: Vec::new(),
+            errors: Vec::new(),
+            pending_error: None,
         }
     }
 
-    pub fn set_content(&mut self, new_content: &'a str) {
-        self.content_len = new_content.len();
-        self.content = new_content;
-        self.iterator = new_content.chars();
+    pub fn set_file(&mut self, file: usize) {
+        self.file = file;
     }
 
     pub fn tokenize(&mut self) {
@@ -69,123 +175,233 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// lex the token starting at the next character, `skip_whitespace` must have run first
     fn match_kind(&mut self) -> TokenKind {
-        while let Some(c) = self.iterator.next() {
-            match c {
-                '#' => {
-                    // NEEDS WORK
-                    let start: usize = self.offset();
-                    loop {
-                        let char_buffer = self.peek();
-                        match char_buffer {
-                            Some(cc) => {
-                                if cc == ' ' || cc == '\n' || self.is_at_end() {
-                                    let value: &str = &self.content[start..self.offset()];
-                                    let value: Result<i32, _> = i32::from_str_radix(value, 10);
-                                    match value {
-                                        Ok(val) => return TokenKind::IntegerOperand { value: val },
-                                        Err(_err) => {
-                                            self.handle_lexical_error(
-                                                "failed to tokenize integer operand",
-                                                self.offset() - self.start_of_line,
-                                            );
-                                            return TokenKind::Eof;
-                                        }
-                                    }
-                                } else {
-                                    self.iterator.next();
-                                }
-                            }
-                            None => {
-                                let value: &str = &self.content[start..self.offset()];
-                                let value: i32 = i32::from_str_radix(value, 10).unwrap();
-                                return TokenKind::IntegerOperand { value };
-                            }
+        let Some(c) = self.iterator.next() else {
+            return TokenKind::Eof;
+        };
+        match c {
+            '#' => {
+                let start: usize = self.offset();
+                let value: Result<TokenKind, String> = if self.peek() == Some('\'') {
+                    self.iterator.next();
+                    match self.consume_char_literal() {
+                        Some(c) => Ok(TokenKind::IntegerOperand { value: c as i32 }),
+                        None => {
+                            self.consume_word(start);
+                            Err("invalid character literal".into())
                         }
                     }
-                    // NEEDS WORK
+                } else {
+                    let literal: &str = self.consume_word(start);
+                    if literal.contains('.') {
+                        parse_float(literal).map(|value| TokenKind::FloatOperand { value })
+                    } else {
+                        parse_integer(literal).map(|value| TokenKind::IntegerOperand { value })
+                    }
+                };
+                match value {
+                    Ok(token_kind) => token_kind,
+                    Err(message) => self.handle_lexical_error(&message),
                 }
-                '$' => {
-                    // NEEDS WORK
-                    let start: usize = self.offset();
-                    loop {
-                        let char_buffer = self.peek();
-                        match char_buffer {
-                            Some(cc) => {
-                                if cc == ' ' || cc == '\n' || self.is_at_end() {
-                                    let value: &str = &self.content[start..self.offset()];
-                                    let value: Result<usize, _> = usize::from_str_radix(value, 10);
-                                    return match value {
-                                        Ok(val) => TokenKind::Register { reg_index: val },
-                                        Err(_err) => {
-                                            self.handle_lexical_error(
-                                                "failed to tokenize register index",
-                                                self.offset() - self.start_of_line,
-                                            );
-                                            TokenKind::Eof
-                                        }
-                                    }
-                                } else {
-                                    self.iterator.next();
-                                }
-                            }
-                            None => {
-                                let value: &str = &self.content[start..self.offset()];
-                                let value: usize = usize::from_str_radix(value, 10).unwrap();
-                                return TokenKind::Register { reg_index: value };
-                            }
-                        }
+            }
+            '$' => {
+                let start: usize = self.offset();
+                let value: &str = self.consume_word(start);
+                match value.parse::<usize>() {
+                    Ok(reg_index) if reg_index < REGISTER_COUNT => {
+                        TokenKind::Register { reg_index }
                     }
-                    // NEEDS WORK
+                    Ok(_) => self.handle_lexical_error(REGISTER_RANGE_ERROR),
+                    Err(_err) => self.handle_lexical_error("failed to tokenize register index"),
                 }
-                ' ' => {}
-                '\n' => {
-                    self.line += 1;
-                    self.start_of_line = self.offset();
+            }
+            '%' => {
+                let start: usize = self.offset();
+                let value: &str = self.consume_word(start);
+                match value.strip_prefix('f').map(str::parse::<usize>) {
+                    Some(Ok(reg_index)) if reg_index < REGISTER_COUNT => {
+                        TokenKind::FloatRegister { reg_index }
+                    }
+                    Some(Ok(_)) => self.handle_lexical_error(REGISTER_RANGE_ERROR),
+                    _ => self.handle_lexical_error("invalid float register, expected %f<index>"),
                 }
-                _ => {
-                    // NEEDS WORK
-                    let start: usize = self.offset();
-                    loop {
-                        let char_buffer = self.peek();
-                        match char_buffer {
-                            Some(cc) => {
-                                if cc == ' ' || cc == '\n' || self.is_at_end() {
-                                    let value: &str = &self.content[start - 1..self.offset()];
-                                    let value: Opcode = Opcode::from(value);
-                                    match value {
-                                        Opcode::NOP => {
-                                            self.handle_lexical_error(
-                                                "failed to tokenize opcode literal",
-                                                self.offset() - self.start_of_line,
-                                            );
-                                            return TokenKind::Eof;
-                                        }
-                                        _ => return TokenKind::Operation { code: value },
-                                    }
-                                } else {
-                                    self.iterator.next();
-                                }
-                            }
-                            None => {
-                                let value: &str = &self.content[start - 1..self.offset()];
-                                let value: Opcode = Opcode::from(value);
-                                return TokenKind::Operation { code: value };
-                            }
-                        }
+            }
+            '.' => {
+                let start: usize = self.offset();
+                let name: &str = self.consume_word(start);
+                if !is_label_name(name) {
+                    return self.handle_lexical_error("invalid directive name");
+                }
+                TokenKind::Directive { name: name.into() }
+            }
+            '"' => match self.consume_string() {
+                Some(value) => TokenKind::StringOperand { value },
+                None => self.handle_lexical_error("unterminated string literal"),
+            },
+            '[' => match self.consume_memory_operand() {
+                Some((base, offset)) => TokenKind::MemoryOperand { base, offset },
+                None => self
+                    .handle_lexical_error("invalid memory operand, expected [ $base + #offset ]"),
+            },
+            '\\' => {
+                let start: usize = self.offset();
+                let name: &str = self.consume_word(start);
+                if !is_label_name(name) {
+                    return self.handle_lexical_error("invalid macro parameter name");
+                }
+                TokenKind::MacroParameter { name: name.into() }
+            }
+            '@' => {
+                let start: usize = self.offset();
+                let name: &str = self.consume_word(start);
+                if !is_label_name(name) {
+                    return self.handle_lexical_error("invalid label name");
+                }
+                TokenKind::LabelUsage { name: name.into() }
+            }
+            _ => {
+                let start: usize = self.offset() - c.len_utf8();
+                let word: &str = self.consume_word(start);
+                if let Some(name) = word.strip_suffix(':') {
+                    if !is_label_name(name) {
+                        return self.handle_lexical_error("invalid label name");
                     }
-                    // NEEDS WORK
+                    return TokenKind::LabelDeclaration { name: name.into() };
+                }
+                if PSEUDO_OPERATIONS.contains(&word) {
+                    return TokenKind::PseudoOperation { name: word.into() };
+                }
+                match Opcode::from(word) {
+                    Opcode::NOP if is_label_name(word) => {
+                        TokenKind::Identifier { name: word.into() }
+                    }
+                    Opcode::NOP => {
+                        self.handle_lexical_error(&format!("unknown mnemonic '{}'", word))
+                    }
+                    code => TokenKind::Operation { code },
                 }
             }
         }
-        TokenKind::Eof
     }
 
     fn next_token(&mut self) -> Token {
+        self.skip_whitespace();
         let start: usize = self.offset();
+        let line: usize = self.line + 1;
+        let column: usize = start - self.start_of_line + 1;
         let token_kind: TokenKind = self.match_kind();
         let end: usize = self.offset();
-        Token::new(token_kind, start, end)
+        let mut token: Token = Token::new(token_kind, start, end);
+        token.line = line;
+        token.column = column;
+        token.file = self.file;
+        if let Some(message) = self.pending_error.take() {
+            self.errors.push(ParseError {
+                message,
+                token: token.clone(),
+            });
+        }
+        token
+    }
+
+    /// skip whitespace, commas separating operands and `;` comments,
+    /// which run to the end of their line
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' | ',' => {}
+                '\n' => {
+                    self.line += 1;
+                    self.start_of_line = self.offset() + 1;
+                }
+                ';' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.iterator.next();
+                    }
+                    continue;
+                }
+                _ => break,
+            }
+            self.iterator.next();
+        }
+    }
+
+    /// consume characters up to the next whitespace or comment and return them, starting at `start`
+    fn consume_word(&mut self, start: usize) -> &'a str {
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || c == ';' || c == ',' {
+                break;
+            }
+            self.iterator.next();
+        }
+        let content: &'a str = self.content;
+        &content[start..self.offset()]
+    }
+
+    /// consume a string literal up to its closing quote, handling \\ \" \n \t and \0 escapes
+    fn consume_string(&mut self) -> Option<String> {
+        let mut value: String = String::new();
+        loop {
+            match self.iterator.next()? {
+                '"' => return Some(value),
+                '\n' => return None,
+                '\\' => match self.iterator.next()? {
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    '0' => value.push('\0'),
+                    c @ ('"' | '\\') => value.push(c),
+                    _ => return None,
+                },
+                c => value.push(c),
+            }
+        }
+    }
+
+    /// consume a character literal after its opening quote, handling the same escapes
+    /// as strings plus \'
+    fn consume_char_literal(&mut self) -> Option<char> {
+        let value: char = match self.iterator.next()? {
+            '\\' => match self.iterator.next()? {
+                'n' => '\n',
+                't' => '\t',
+                '0' => '\0',
+                c @ ('\'' | '\\') => c,
+                _ => return None,
+            },
+            '\'' | '\n' => return None,
+            c => c,
+        };
+        match self.iterator.next()? {
+            '\'' => Some(value),
+            _ => None,
+        }
+    }
+
+    /// consume a memory operand up to its closing bracket, `[ $base ]` or `[ $base + #offset ]`
+    fn consume_memory_operand(&mut self) -> Option<(usize, i32)> {
+        let start: usize = self.offset();
+        loop {
+            match self.iterator.next()? {
+                ']' => break,
+                '\n' => return None,
+                _ => {}
+            }
+        }
+        let inner: &str = &self.content[start..self.offset() - 1];
+        let (base, offset): (&str, Option<&str>) = match inner.split_once('+') {
+            Some((base, offset)) => (base, Some(offset)),
+            None => (inner, None),
+        };
+        let base: usize = base.trim().strip_prefix('$')?.parse::<usize>().ok()?;
+        if base >= REGISTER_COUNT {
+            return None;
+        }
+        let offset: i32 = match offset {
+            Some(offset) => parse_integer(offset.trim().strip_prefix('#')?).ok()?,
+            None => 0,
+        };
+        Some((base, offset))
     }
 
     /// does not return a ASCII encoded value (0-255) but an utf8 one (0 - 0x10FFFF)
@@ -196,19 +412,66 @@ impl<'a> Lexer<'a> {
 
     /// the offset is the difference between the total nb of chars and the remaining number of char
     fn offset(&self) -> usize {
-        self.content.len() - self.iterator.as_str().len()
+        self.content_len - self.iterator.as_str().len()
     }
 
-    fn is_at_end(&self) -> bool {
-        self.offset() >= self.content.len()
+    fn handle_lexical_error(&mut self, message: &str) -> TokenKind {
+        self.pending_error = Some(message.into());
+        TokenKind::Invalid
     }
+}
 
-    fn handle_lexical_error(&self, msg_buffer: &str, start_of_line: usize) {
-        let buffer: String = format!(
-            "[ERROR] Lexical error : {} at {}:{}",
-            msg_buffer, self.line, start_of_line
-        );
-        println!("{}", buffer.as_str());
+/// decimal, `0x` hexadecimal or `0b` binary integer, with an optional leading `-`
+fn parse_integer(literal: &str) -> Result<i32, String> {
+    let (negative, digits): (bool, &str) = match literal.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, literal),
+    };
+    let (radix, digits): (u32, &str) = if let Some(hex) = digits.strip_prefix("0x") {
+        (16, hex)
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        (2, binary)
+    } else {
+        (10, digits)
+    };
+    // from_str_radix accepts a sign of its own
+    if digits.starts_with(['+', '-']) {
+        return Err(format!("invalid integer literal '{}'", literal));
+    }
+    let value: i64 = match i64::from_str_radix(digits, radix) {
+        Ok(value) => value,
+        Err(err) => {
+            return Err(match err.kind() {
+                IntErrorKind::PosOverflow => {
+                    format!("integer literal '{}' is out of range", literal)
+                }
+                _ => format!("invalid integer literal '{}'", literal),
+            })
+        }
+    };
+    let value: i64 = if negative { -value } else { value };
+    i32::try_from(value).map_err(|_| format!("integer literal '{}' is out of range", literal))
+}
+
+/// decimal literal with a fractional part and an optional exponent, `3.14`, `-0.5` or `1.5e3`
+fn parse_float(literal: &str) -> Result<f64, String> {
+    let is_decimal: bool = literal
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'));
+    match literal.parse::<f64>() {
+        Ok(value) if is_decimal && value.is_finite() => Ok(value),
+        Ok(_) if is_decimal => Err(format!("float literal '{}' is out of range", literal)),
+        _ => Err(format!("invalid float literal '{}'", literal)),
+    }
+}
+
+fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
     }
 }
 
@@ -222,7 +485,7 @@ mod test {
         let mut lexer: Lexer = Lexer::new(content, content.len());
         lexer.tokenize();
         assert_eq!(
-            lexer.tokens.get(0).unwrap().token_kind,
+            lexer.tokens.first().unwrap().token_kind,
             TokenKind::Operation { code: Opcode::LOAD }
         )
     }
@@ -267,4 +530,201 @@ mod test {
             TokenKind::Operation { code: Opcode::DIV }
         );
     }
+
+    #[test]
+    fn label_tokens() {
+        let content: &str = "loop: INC $0\nLA $1 @loop\nJMP $1";
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        let kinds: Vec<TokenKind> = lexer.tokens.iter().map(|t| t.token_kind.clone()).collect();
+        assert_eq!(
+            kinds[..5],
+            [
+                TokenKind::LabelDeclaration {
+                    name: "loop".into()
+                },
+                TokenKind::Operation { code: Opcode::INC },
+                TokenKind::Register { reg_index: 0 },
+                TokenKind::PseudoOperation { name: "LA".into() },
+                TokenKind::Register { reg_index: 1 },
+            ]
+        );
+        assert_eq!(
+            kinds[5],
+            TokenKind::LabelUsage {
+                name: "loop".into()
+            }
+        );
+        assert_eq!((lexer.tokens[5].line(), lexer.tokens[5].column()), (2, 7))
+    }
+
+    #[test]
+    fn invalid_label_name() {
+        let content: &str = "1loop: HLT";
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        assert_eq!(lexer.tokens.first().unwrap().token_kind, TokenKind::Invalid);
+        assert_eq!(lexer.errors[0].message, "invalid label name")
+    }
+
+    #[test]
+    fn directive_tokens() {
+        let content: &str = ".data\nmsg: .asciiz \"hi \\\"you\\\"\\n\"\n.code";
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        let kinds: Vec<TokenKind> = lexer.tokens.iter().map(|t| t.token_kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Directive {
+                    name: "data".into()
+                },
+                TokenKind::LabelDeclaration { name: "msg".into() },
+                TokenKind::Directive {
+                    name: "asciiz".into()
+                },
+                TokenKind::StringOperand {
+                    value: "hi \"you\"\n".into()
+                },
+                TokenKind::Directive {
+                    name: "code".into()
+                },
+                TokenKind::Eof,
+            ]
+        )
+    }
+
+    #[test]
+    fn unterminated_string() {
+        let content: &str = ".asciiz \"oops\nHLT";
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        assert_eq!(lexer.tokens.get(1).unwrap().token_kind, TokenKind::Invalid)
+    }
+
+    #[test]
+    fn memory_operand_tokens() {
+        let content: &str = "STOREW [ $1 + #8 ] $2\nLOADB $0 [$3]\nLOADB $0 [ $3 + ]";
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        let kinds: Vec<TokenKind> = lexer.tokens.iter().map(|t| t.token_kind.clone()).collect();
+        assert_eq!(kinds[1], TokenKind::MemoryOperand { base: 1, offset: 8 });
+        assert_eq!(kinds[2], TokenKind::Register { reg_index: 2 });
+        assert_eq!(kinds[5], TokenKind::MemoryOperand { base: 3, offset: 0 });
+        assert_eq!(kinds[8], TokenKind::Invalid)
+    }
+
+    #[test]
+    fn continue_after_errors() {
+        let content: &str = "LA?D $1 #5\nADD $0 $x $2\nHLT";
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        assert_eq!(lexer.errors.len(), 2);
+        assert_eq!(lexer.errors[0].message, "unknown mnemonic 'LA?D'");
+        let token: &Token = &lexer.errors[1].token;
+        assert_eq!((token.line(), token.column()), (2, 8));
+        assert_eq!((token.start(), token.end()), (18, 20));
+        assert_eq!(
+            lexer.tokens[lexer.tokens.len() - 2].token_kind,
+            TokenKind::Operation { code: Opcode::HLT }
+        )
+    }
+
+    #[test]
+    fn literals_and_comments() {
+        let content: &str =
+            "; header\r\nLOAD $1 #0x1F4 ; load\r\n\tLOAD $2 #0b1010\nLOAD $3 #'A';x\nLOAD $4 #-5\nLOAD $5 #'\\n'";
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        assert_eq!(lexer.errors, []);
+        let values: Vec<i32> = lexer
+            .tokens
+            .iter()
+            .filter_map(|t| match t.token_kind {
+                TokenKind::IntegerOperand { value } => Some(value),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(values, [500, 10, 65, -5, 10]);
+        assert_eq!(lexer.tokens.len(), 16);
+        assert_eq!((lexer.tokens[3].line(), lexer.tokens[3].column()), (3, 2))
+    }
+
+    #[test]
+    fn malformed_literals() {
+        for (content, message) in [
+            ("LOAD $1 #0xZZ", "invalid integer literal '0xZZ'"),
+            ("LOAD $1 #--5", "invalid integer literal '--5'"),
+            (
+                "LOAD $1 #99999999999",
+                "integer literal '99999999999' is out of range",
+            ),
+            ("LOAD $1 #'AB'", "invalid character literal"),
+            ("LOAD $1 #", "invalid integer literal ''"),
+            ("LOAD $", "failed to tokenize register index"),
+            ("LOADF %f1 #1.2.3", "invalid float literal '1.2.3'"),
+            (
+                "LOADF %f1 #1.0e999",
+                "float literal '1.0e999' is out of range",
+            ),
+            ("ADDF %g1", "invalid float register, expected %f<index>"),
+            ("LOAD $32 #1", REGISTER_RANGE_ERROR),
+            ("LOAD $300 #1", REGISTER_RANGE_ERROR),
+            ("ADDF %f40 %f1 %f2", REGISTER_RANGE_ERROR),
+            (
+                "LOADW $1 [ $32 ]",
+                "invalid memory operand, expected [ $base + #offset ]",
+            ),
+        ] {
+            let mut lexer: Lexer = Lexer::new(content, content.len());
+            lexer.tokenize();
+            assert_eq!(lexer.errors.len(), 1, "{}", content);
+            assert_eq!(lexer.errors[0].message, message)
+        }
+    }
+
+    #[test]
+    fn float_tokens() {
+        let content: &str = "LOADF %f2 #-3.25\nADDF %f0 %f31 %f1 ; sum\n.double #1.5e3 #2";
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        assert_eq!(lexer.errors, []);
+        let kinds: Vec<&TokenKind> = lexer.tokens.iter().map(|t| &t.token_kind).collect();
+        assert_eq!(
+            kinds[..3],
+            [
+                &TokenKind::PseudoOperation {
+                    name: "LOADF".into()
+                },
+                &TokenKind::FloatRegister { reg_index: 2 },
+                &TokenKind::FloatOperand { value: -3.25 },
+            ]
+        );
+        assert_eq!(kinds[5], &TokenKind::FloatRegister { reg_index: 31 });
+        assert_eq!(kinds[8], &TokenKind::FloatOperand { value: 1500.0 });
+        assert_eq!(kinds[9], &TokenKind::IntegerOperand { value: 2 })
+    }
+
+    #[test]
+    fn macro_tokens() {
+        let content: &str = ".macro twice reg, value\nLOAD \\reg \\value\n.endm\ntwice $1, #2";
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        let kinds: Vec<TokenKind> = lexer.tokens.iter().map(|t| t.token_kind.clone()).collect();
+        assert_eq!(
+            kinds[1],
+            TokenKind::Identifier {
+                name: "twice".into()
+            }
+        );
+        assert_eq!(
+            kinds[3],
+            TokenKind::Identifier {
+                name: "value".into()
+            }
+        );
+        assert_eq!(kinds[5], TokenKind::MacroParameter { name: "reg".into() });
+        assert_eq!(kinds[9], TokenKind::Register { reg_index: 1 });
+        assert_eq!(kinds[10], TokenKind::IntegerOperand { value: 2 })
+    }
 }
@@ -0,0 +1,308 @@
+use std::fmt;
+
+use crate::{executable::Executable, instruction::Opcode, vm::INSTRUCTION_WIDTH};
+
+use super::symbols::{Section, Symbol, SymbolTable};
+
+/// alignment of the data of every object in the linked data section,
+/// so that `.align` directives up to a word still hold once linked
+const DATA_ALIGNMENT: usize = 4;
+
+/// a 16-bit label address in the code of an object, patched by the linker
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relocation {
+    /// byte offset of the address in the code
+    pub offset: usize,
+    pub symbol: String,
+}
+
+/// a source file assembled on its own, its code and data addresses start at 0
+/// and are moved to their place in the executable by `link`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    /// name of the object in link errors, usually its source file
+    pub name: String,
+    pub code: Vec<u8>,
+    pub ro_data: Vec<u8>,
+    /// labels defined in the object
+    pub symbols: SymbolTable,
+    /// labels other objects can reference
+    pub globals: Vec<String>,
+    /// labels defined by another object
+    pub externs: Vec<String>,
+    pub relocations: Vec<Relocation>,
+    /// (code offset, source line) of every instruction
+    pub line_table: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkError {
+    DuplicateSymbol {
+        name: String,
+        first: String,
+        second: String,
+    },
+    UndefinedSymbol {
+        name: String,
+        object: String,
+    },
+    AddressOverflow {
+        name: String,
+        object: String,
+    },
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::DuplicateSymbol {
+                name,
+                first,
+                second,
+            } => write!(
+                f,
+                "global symbol '{}' is defined by both {} and {}",
+                name, first, second
+            ),
+            LinkError::UndefinedSymbol { name, object } => write!(
+                f,
+                "{} references '{}', which no object defines as .global",
+                object, name
+            ),
+            LinkError::AddressOverflow { name, object } => write!(
+                f,
+                "address of '{}' referenced by {} does not fit in 16 bits",
+                name, object
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LinkError {}
+
+/// place the objects one after the other in the code and data sections
+/// and patch every label reference with its final address
+/// the entry point is the `main` label of the first object defining one
+pub fn link(objects: &[Object]) -> Result<Executable, LinkError> {
+    let mut code_bases: Vec<usize> = Vec::new();
+    let mut data_bases: Vec<usize> = Vec::new();
+    let mut code_len: usize = 0;
+    let mut data_len: usize = 0;
+    for object in objects {
+        data_len = data_len.next_multiple_of(DATA_ALIGNMENT);
+        code_bases.push(code_len);
+        data_bases.push(data_len);
+        code_len += object.code.len();
+        data_len += object.ro_data.len();
+    }
+
+    let address = |index: usize, symbol: &Symbol| -> usize {
+        match symbol.section {
+            Section::Code => code_bases[index] + symbol.offset,
+            Section::Data => data_bases[index] + symbol.offset,
+        }
+    };
+
+    let mut globals: Vec<(&str, usize, usize)> = Vec::new();
+    for (index, object) in objects.iter().enumerate() {
+        for name in &object.globals {
+            if let Some((_, first, _)) = globals.iter().find(|(global, _, _)| global == name) {
+                return Err(LinkError::DuplicateSymbol {
+                    name: name.clone(),
+                    first: objects[*first].name.clone(),
+                    second: object.name.clone(),
+                });
+            }
+            if let Some(symbol) = object.symbols.iter().find(|symbol| symbol.name == *name) {
+                globals.push((name, index, address(index, symbol)));
+            }
+        }
+    }
+
+    for object in objects {
+        if let Some(name) = object
+            .externs
+            .iter()
+            .find(|name| !globals.iter().any(|(global, _, _)| global == name))
+        {
+            return Err(LinkError::UndefinedSymbol {
+                name: name.clone(),
+                object: object.name.clone(),
+            });
+        }
+    }
+
+    let mut executable: Executable = Executable::default();
+    for (index, object) in objects.iter().enumerate() {
+        let mut code: Vec<u8> = object.code.clone();
+        for relocation in &object.relocations {
+            let local: Option<&Symbol> = object
+                .symbols
+                .iter()
+                .find(|symbol| symbol.name == relocation.symbol);
+            let target: usize = match local {
+                Some(symbol) => address(index, symbol),
+                None => match globals
+                    .iter()
+                    .find(|(name, _, _)| *name == relocation.symbol)
+                {
+                    Some((_, _, target)) => *target,
+                    None => {
+                        return Err(LinkError::UndefinedSymbol {
+                            name: relocation.symbol.clone(),
+                            object: object.name.clone(),
+                        })
+                    }
+                },
+            };
+            // LA loads the address with LOAD, which sign-extends it
+            let opcode: u8 = code[relocation.offset - relocation.offset % INSTRUCTION_WIDTH];
+            let limit: usize = if opcode == Opcode::LOAD as u8 {
+                i16::MAX as usize
+            } else {
+                u16::MAX as usize
+            };
+            if target > limit {
+                return Err(LinkError::AddressOverflow {
+                    name: relocation.symbol.clone(),
+                    object: object.name.clone(),
+                });
+            }
+            let target: u16 = target as u16;
+            code[relocation.offset..relocation.offset + 2].copy_from_slice(&target.to_be_bytes());
+        }
+        executable.code.append(&mut code);
+        executable.ro_data.resize(data_bases[index], 0);
+        executable.ro_data.extend_from_slice(&object.ro_data);
+        for symbol in object.symbols.iter() {
+            executable.symbols.add_symbol(Symbol::new(
+                &symbol.name,
+                address(index, symbol),
+                symbol.section,
+            ));
+        }
+        for (offset, line) in &object.line_table {
+            executable
+                .line_table
+                .push((code_bases[index] + offset, *line));
+        }
+    }
+    executable.entry_point = objects
+        .iter()
+        .enumerate()
+        .find_map(|(index, object)| {
+            object
+                .symbols
+                .iter()
+                .find(|symbol| symbol.name == "main" && symbol.section == Section::Code)
+                .map(|symbol| address(index, symbol))
+        })
+        .unwrap_or(0);
+    Ok(executable)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        assembler::assemble,
+        vm::{error::ExitReason, VM},
+    };
+
+    use super::*;
+
+    fn object(name: &str, source: &str) -> Object {
+        assemble(source).unwrap().to_object(name).unwrap()
+    }
+
+    #[test]
+    fn link_single_object() {
+        let source: &str = ".data\nmsg: .asciiz \"hi\"\n.code\nmain: LA $1 @msg\nHLT";
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            link(&[program.to_object("main.asm").unwrap()]),
+            Ok(program.to_executable().unwrap())
+        )
+    }
+
+    #[test]
+    fn link_objects() {
+        // lib doubles its argument and exports a data word
+        let lib: Object = object(
+            "lib.asm",
+            ".global double, calls\n.data\n.byte #1\ncalls: .word #7\n.code\n\
+             double: ADD $0 $0 $0\nRET",
+        );
+        let main: Object = object(
+            "main.asm",
+            ".extern double, calls\n.data\n.byte #1 #2 #3\n.code\n\
+             main: LOAD $0 #21\nLA $30 @double\nCALL $30\nLA $5 @calls\nHLT",
+        );
+        assert_eq!(main.relocations.len(), 2);
+        let executable: Executable = link(&[main, lib]).unwrap();
+        assert_eq!(executable.symbols.symbol_offset("double"), Some(20));
+        assert_eq!(executable.symbols.symbol_offset("calls"), Some(5));
+        assert_eq!(executable.ro_data[4..9], [1, 0, 0, 0, 7]);
+        let mut vm: VM = VM::new();
+        vm.load(&executable);
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!((vm.registers[0], vm.registers[5]), (42, 5))
+    }
+
+    #[test]
+    fn link_float_literals() {
+        let lib: Object = object(
+            "lib.asm",
+            ".global half\n.data\n.byte #1\n.code\nhalf: LOADF %f1 #0.5\nMULF %f0 %f1 %f0\nRET",
+        );
+        let main: Object = object(
+            "main.asm",
+            ".extern half\nmain: LOADF %f0 #3.0\nLA $30 @half\nCALL $30\nHLT",
+        );
+        let mut vm: VM = VM::new();
+        vm.load(&link(&[main, lib]).unwrap());
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(vm.float_registers[0], 1.5)
+    }
+
+    #[test]
+    fn link_errors() {
+        let lib: Object = object("lib.asm", ".global twice\ntwice: HLT");
+        let main: Object = object("main.asm", ".extern twice, other\nLA $1 @other");
+        assert_eq!(
+            link(&[main.clone(), lib.clone()]),
+            Err(LinkError::UndefinedSymbol {
+                name: "other".into(),
+                object: "main.asm".into()
+            })
+        );
+        assert_eq!(
+            link(&[lib.clone(), lib]).unwrap_err().to_string(),
+            "global symbol 'twice' is defined by both lib.asm and lib.asm"
+        );
+        assert!(assemble(".global nope\nHLT").is_err());
+        assert!(assemble(".extern twice\ntwice: HLT").is_err());
+        assert!(assemble(".extern twice\nLA $1 @twice")
+            .unwrap()
+            .to_executable()
+            .is_err())
+    }
+
+    #[test]
+    fn link_standard_library() {
+        let std: Object = object("std.asm", include_str!("../../lib/std.asm"));
+        let main: Object = object(
+            "main.asm",
+            ".extern max, abs, strlen\n.data\nname: .asciiz \"spectrum\"\n.code\nLOAD $20 #0\n\
+             LA $30 @strlen\nLA $0 @name\nCALL $30\nADD $0 $20 $16\n\
+             LA $30 @abs\nLOAD $1 #12\nSUB $20 $1 $0\nCALL $30\nADD $0 $20 $17\n\
+             LA $30 @max\nLOAD $0 #3\nLOAD $1 #7\nCALL $30\nHLT",
+        );
+        let mut vm: VM = VM::new();
+        vm.load(&link(&[main, std]).unwrap());
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(
+            (vm.registers[16], vm.registers[17], vm.registers[0]),
+            (8, 12, 7)
+        )
+    }
+}
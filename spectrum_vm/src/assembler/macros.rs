@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+
+use super::{
+    lexer::{Token, TokenKind},
+    parser::ParseError,
+};
+
+/// nesting depth after which an expansion is considered to recurse forever
+const MAX_EXPANSION_DEPTH: usize = 64;
+/// number of tokens all the expansions of a source may produce, macros invoking
+/// each other several times grow exponentially without recursing
+const MAX_EXPANDED_TOKENS: usize = 100_000;
+
+/// a `.macro name param1, param2 ... .endm` definition
+struct Macro {
+    /// name token of the definition
+    name: Token,
+    parameters: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// expands macro definitions and invocations of a token stream before it is parsed
+/// tokens of an expansion keep the span of the macro body they come from and
+/// point to their invocation through `Token::expansion`
+#[derive(Default)]
+pub struct MacroExpander {
+    macros: HashMap<String, Macro>,
+    /// number of expansions so far, used to give macro-local labels unique names
+    expansion_count: usize,
+    /// number of tokens produced by the expansions so far
+    expanded_tokens: usize,
+    pub errors: Vec<ParseError>,
+}
+
+impl MacroExpander {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// remove the definitions from `tokens` and replace every invocation by its body
+    pub fn expand(&mut self, tokens: Vec<Token>) -> Vec<Token> {
+        let mut expanded: Vec<Token> = Vec::new();
+        self.expand_into(tokens, 0, &mut expanded);
+        expanded
+    }
+
+    fn expand_into(&mut self, tokens: Vec<Token>, depth: usize, output: &mut Vec<Token>) {
+        let mut iterator = tokens.into_iter().peekable();
+        while let Some(token) = iterator.next() {
+            match &token.token_kind {
+                TokenKind::Directive { name } if name == "macro" => {
+                    let mut definition: Vec<Token> = Vec::new();
+                    while let Some(next) = iterator.next_if(|next| {
+                        !is_directive(next, "endm") && next.token_kind != TokenKind::Eof
+                    }) {
+                        definition.push(next);
+                    }
+                    if iterator
+                        .next_if(|next| is_directive(next, "endm"))
+                        .is_none()
+                    {
+                        self.error("unterminated macro, expected .endm".into(), &token);
+                    }
+                    self.define(&token, definition);
+                }
+                TokenKind::Directive { name } if name == "endm" => {
+                    self.error(".endm without a matching .macro".into(), &token);
+                }
+                TokenKind::Identifier { name } if self.macros.contains_key(name) => {
+                    let mut arguments: Vec<Token> = Vec::new();
+                    while let Some(next) = iterator.next_if(|next| is_argument(next, &token)) {
+                        arguments.push(next);
+                    }
+                    self.invoke(&token, arguments, depth, output);
+                }
+                TokenKind::MacroParameter { name } => {
+                    let message: String =
+                        format!("macro parameter \\{} used outside of a macro", name);
+                    self.error(message, &token);
+                    let mut invalid: Token = token.clone();
+                    invalid.token_kind = TokenKind::Invalid;
+                    output.push(invalid);
+                }
+                _ => output.push(token),
+            }
+        }
+    }
+
+    /// record the macro declared by `directive`, its name and parameters are the
+    /// identifiers on the directive line, the rest of `definition` is its body
+    fn define(&mut self, directive: &Token, definition: Vec<Token>) {
+        let mut definition = definition.into_iter().peekable();
+        let (name, macro_name): (Token, String) = match definition.next_if(|next| {
+            matches!(next.token_kind, TokenKind::Identifier { .. })
+                && next.line() == directive.line()
+        }) {
+            Some(name) => match &name.token_kind {
+                TokenKind::Identifier { name: macro_name } => (name.clone(), macro_name.clone()),
+                _ => return,
+            },
+            None => return self.error("expected a macro name after .macro".into(), directive),
+        };
+        let mut parameters: Vec<String> = Vec::new();
+        while let Some(parameter) = definition.next_if(|next| next.line() == directive.line()) {
+            match &parameter.token_kind {
+                TokenKind::Identifier { name } if !parameters.contains(name) => {
+                    parameters.push(name.clone())
+                }
+                TokenKind::Identifier { name } => {
+                    self.error(format!("duplicate macro parameter '{}'", name), &parameter)
+                }
+                _ => self.error("expected a macro parameter name".into(), &parameter),
+            }
+        }
+        let body: Vec<Token> = definition.collect();
+        for token in &body {
+            match &token.token_kind {
+                TokenKind::MacroParameter { name } if !parameters.contains(name) => {
+                    self.error(format!("unknown macro parameter \\{}", name), token)
+                }
+                TokenKind::Directive { name } if name == "macro" => {
+                    self.error("macro definitions cannot be nested".into(), token)
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(first) = self.macros.get(&macro_name) {
+            let message: String = format!(
+                "duplicate macro '{}', first defined at {}:{}",
+                macro_name,
+                first.name.line(),
+                first.name.column()
+            );
+            return self.error(message, &name);
+        }
+        self.macros.insert(
+            macro_name,
+            Macro {
+                name,
+                parameters,
+                body,
+            },
+        );
+    }
+
+    /// expand the macro invoked by `invocation` with `arguments` for its parameters,
+    /// then the invocations its body contains
+    fn invoke(
+        &mut self,
+        invocation: &Token,
+        arguments: Vec<Token>,
+        depth: usize,
+        output: &mut Vec<Token>,
+    ) {
+        let name: &str = match &invocation.token_kind {
+            TokenKind::Identifier { name } => name,
+            _ => return,
+        };
+        let definition: &Macro = &self.macros[name];
+        if arguments.len() != definition.parameters.len() {
+            let message: String = format!(
+                "macro '{}' expects {} argument(s), found {} (defined at {}:{})",
+                name,
+                definition.parameters.len(),
+                arguments.len(),
+                definition.name.line(),
+                definition.name.column()
+            );
+            return self.error(message, invocation);
+        }
+        if depth == MAX_EXPANSION_DEPTH {
+            let message: String = format!("macro '{}' expands itself recursively", name);
+            return self.error(message, invocation);
+        }
+        if self.expanded_tokens > MAX_EXPANDED_TOKENS {
+            return;
+        }
+        self.expanded_tokens += definition.body.len();
+        if self.expanded_tokens > MAX_EXPANDED_TOKENS {
+            let message: String = format!(
+                "macro expansion produces more than {} tokens, stopped at '{}'",
+                MAX_EXPANDED_TOKENS, name
+            );
+            return self.error(message, invocation);
+        }
+
+        self.expansion_count += 1;
+        let local_labels: Vec<&str> = definition
+            .body
+            .iter()
+            .filter_map(|token| match &token.token_kind {
+                TokenKind::LabelDeclaration { name } => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        // labels of the body get a name unique to the expansion, which no
+        // label of the source can have
+        let local_name = |label: &str| format!("{}.{}.{}", name, self.expansion_count, label);
+        let mut expansion: Vec<Token> = Vec::new();
+        for token in &definition.body {
+            let token_kind: TokenKind = match &token.token_kind {
+                TokenKind::MacroParameter { name } => {
+                    match definition
+                        .parameters
+                        .iter()
+                        .position(|parameter| parameter == name)
+                    {
+                        Some(index) => arguments[index].token_kind.clone(),
+                        None => TokenKind::Invalid,
+                    }
+                }
+                TokenKind::LabelDeclaration { name } if local_labels.contains(&name.as_str()) => {
+                    TokenKind::LabelDeclaration {
+                        name: local_name(name),
+                    }
+                }
+                TokenKind::LabelUsage { name } if local_labels.contains(&name.as_str()) => {
+                    TokenKind::LabelUsage {
+                        name: local_name(name),
+                    }
+                }
+                token_kind => token_kind.clone(),
+            };
+            expansion.push(token.expanded(token_kind, invocation));
+        }
+        self.expand_into(expansion, depth + 1, output);
+    }
+
+    fn error(&mut self, message: String, token: &Token) {
+        self.errors.push(ParseError {
+            message,
+            token: token.clone(),
+        });
+    }
+}
+
+fn is_directive(token: &Token, directive: &str) -> bool {
+    matches!(&token.token_kind, TokenKind::Directive { name } if name == directive)
+}
+
+/// operand tokens following a macro name, invalid tokens only on its line
+fn is_argument(token: &Token, invocation: &Token) -> bool {
+    match token.token_kind {
+        TokenKind::Register { .. }
+        | TokenKind::IntegerOperand { .. }
+        | TokenKind::FloatRegister { .. }
+        | TokenKind::FloatOperand { .. }
+        | TokenKind::LabelUsage { .. }
+        | TokenKind::StringOperand { .. }
+        | TokenKind::MemoryOperand { .. } => true,
+        TokenKind::Invalid => token.line() == invocation.line(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::assembler::lexer::Lexer;
+
+    use super::*;
+
+    fn expand_source(content: &str) -> (Vec<TokenKind>, Vec<ParseError>) {
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        let mut expander: MacroExpander = MacroExpander::new();
+        let tokens: Vec<Token> = expander.expand(lexer.tokens);
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|t| t.token_kind).collect();
+        (kinds, expander.errors)
+    }
+
+    #[test]
+    fn substitute_parameters() {
+        let (kinds, errors) =
+            expand_source(".macro set reg, value\nLOAD \\reg \\value\n.endm\nset $3, #7\nHLT");
+        assert_eq!(errors, []);
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::Operation {
+                    code: crate::instruction::Opcode::LOAD
+                },
+                TokenKind::Register { reg_index: 3 },
+                TokenKind::IntegerOperand { value: 7 },
+                TokenKind::Operation {
+                    code: crate::instruction::Opcode::HLT
+                },
+                TokenKind::Eof,
+            ]
+        )
+    }
+
+    #[test]
+    fn local_labels() {
+        let (kinds, errors) = expand_source(
+            ".macro spin\nagain: JMP @again\n.endm\n.macro twice\nspin\nspin\n.endm\ntwice",
+        );
+        assert_eq!(errors, []);
+        let labels: Vec<&TokenKind> = kinds
+            .iter()
+            .filter(|kind| {
+                matches!(
+                    kind,
+                    TokenKind::LabelDeclaration { .. } | TokenKind::LabelUsage { .. }
+                )
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                &TokenKind::LabelDeclaration {
+                    name: "spin.2.again".into()
+                },
+                &TokenKind::LabelUsage {
+                    name: "spin.2.again".into()
+                },
+                &TokenKind::LabelDeclaration {
+                    name: "spin.3.again".into()
+                },
+                &TokenKind::LabelUsage {
+                    name: "spin.3.again".into()
+                },
+            ]
+        )
+    }
+
+    #[test]
+    fn definition_errors() {
+        let (_, errors) = expand_source(
+            ".macro m a, a\nINC \\b\n.endm\nm $1 $2\n.macro m\n.endm\n.endm\nINC \\a\n.macro r\nr\n.endm\nr\n.macro",
+        );
+        let messages: Vec<&str> = errors.iter().map(|err| err.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "duplicate macro parameter 'a'",
+                "unknown macro parameter \\b",
+                "macro 'm' expects 1 argument(s), found 2 (defined at 1:8)",
+                "duplicate macro 'm', first defined at 1:8",
+                ".endm without a matching .macro",
+                "macro parameter \\a used outside of a macro",
+                "macro 'r' expands itself recursively",
+                "unterminated macro, expected .endm",
+                "expected a macro name after .macro",
+            ]
+        )
+    }
+
+    #[test]
+    fn expansion_limit() {
+        // every macro invokes the previous one twice, 2^20 expansions of m0
+        let mut content: String = ".macro m0\nNOP\n.endm\n".into();
+        for level in 1..=20 {
+            content.push_str(&format!(
+                ".macro m{}\nm{}\nm{}\n.endm\n",
+                level,
+                level - 1,
+                level - 1
+            ));
+        }
+        content.push_str("m20\nm20");
+        let (_, errors) = expand_source(&content);
+        let messages: Vec<&str> = errors.iter().map(|err| err.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            ["macro expansion produces more than 100000 tokens, stopped at 'm1'"]
+        )
+    }
+}
@@ -1,15 +1,54 @@
-use crate::instruction::Opcode;
+use std::{fmt, iter::Peekable, ops::RangeInclusive, slice::Iter};
 
-use super::lexer::{Token, TokenKind};
+use crate::{
+    instruction::{Opcode, OperandKind},
+    vm::INSTRUCTION_WIDTH,
+};
+
+use super::{
+    lexer::{Token, TokenKind},
+    symbols::{Section, SymbolTable},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub token: Token,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Parsing error : {} at {}:{}",
+            self.message,
+            self.token.line(),
+            self.token.column()
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
 
 #[derive(Debug, PartialEq)]
 pub struct AssemblyInstruction {
-    opcode: Token,
+    opcode: Option<Token>,
+    label: Option<Token>,
+    directive: Option<Token>,
     operand_1: Option<Token>,
     operand_2: Option<Token>,
     operand_3: Option<Token>,
+    /// values of a data directive, which can take any number of them
+    directive_operands: Vec<Token>,
 }
 
+/// section switching directives
+pub const SECTION_DIRECTIVES: [&str; 2] = ["code", "data"];
+/// directives exporting symbols to other objects and importing them from other objects
+pub const LINKAGE_DIRECTIVES: [&str; 2] = ["global", "extern"];
+/// directives that emit bytes in the data section
+pub const DATA_DIRECTIVES: [&str; 6] = ["asciiz", "byte", "word", "double", "space", "align"];
+
 impl AssemblyInstruction {
     pub fn new(
         opcode: Token,
@@ -18,51 +57,303 @@ impl AssemblyInstruction {
         operand_3: Option<Token>,
     ) -> Self {
         Self {
-            opcode,
+            opcode: Some(opcode),
+            label: None,
+            directive: None,
             operand_1,
             operand_2,
             operand_3,
+            directive_operands: Vec::new(),
+        }
+    }
+
+    /// label declaration line, takes no space in the assembled program
+    pub fn label(label: Token) -> Self {
+        Self {
+            opcode: None,
+            label: Some(label),
+            directive: None,
+            operand_1: None,
+            operand_2: None,
+            operand_3: None,
+            directive_operands: Vec::new(),
+        }
+    }
+
+    pub fn directive(directive: Token, directive_operands: Vec<Token>) -> Self {
+        Self {
+            opcode: None,
+            label: None,
+            directive: Some(directive),
+            operand_1: None,
+            operand_2: None,
+            operand_3: None,
+            directive_operands,
+        }
+    }
+
+    pub fn directive_name(&self) -> Option<&str> {
+        match &self.directive {
+            Some(Token {
+                token_kind: TokenKind::Directive { name },
+                ..
+            }) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn directive_token(&self) -> Option<&Token> {
+        self.directive.as_ref()
+    }
+
+    /// symbol names given to a .global or .extern directive
+    pub fn symbol_names(&self) -> impl Iterator<Item = (&str, &Token)> {
+        self.directive_operands
+            .iter()
+            .filter_map(|token| match &token.token_kind {
+                TokenKind::Identifier { name } => Some((name.as_str(), token)),
+                _ => None,
+            })
+    }
+
+    /// byte position in the encoded instruction of every label operand
+    /// branch offsets are relative, they need no patching when the code is moved
+    pub fn label_operands(&self) -> Vec<(usize, &Token)> {
+        let mut label_operands: Vec<(usize, &Token)> = Vec::new();
+        if self.is_branch() {
+            return label_operands;
+        }
+        let mut position: usize = 1;
+        for token in [&self.operand_1, &self.operand_2, &self.operand_3]
+            .into_iter()
+            .flatten()
+        {
+            match token.token_kind {
+                TokenKind::Register { .. } | TokenKind::FloatRegister { .. } => position += 1,
+                TokenKind::LabelUsage { .. } => {
+                    label_operands.push((position, token));
+                    position += 2
+                }
+                _ => position += 2,
+            }
+        }
+        label_operands
+    }
+
+    pub fn opcode_token(&self) -> Option<&Token> {
+        self.opcode.as_ref()
+    }
+
+    /// relative branch instruction, taking an offset operand
+    fn is_branch(&self) -> bool {
+        match &self.opcode {
+            Some(Token {
+                token_kind: TokenKind::Operation { code },
+                ..
+            }) => code.operand_kinds() == [OperandKind::Offset],
+            _ => false,
+        }
+    }
+
+    /// label a relative branch goes to
+    pub fn branch_label(&self) -> Option<(&str, &Token)> {
+        match &self.operand_1 {
+            Some(token) if self.is_branch() => match &token.token_kind {
+                TokenKind::LabelUsage { name } => Some((name, token)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// encode a data directive placed at `offset` in the data section
+    pub fn data_bytes(&self, offset: usize) -> Result<Vec<u8>, ParseError> {
+        let mut data: Vec<u8> = Vec::new();
+        if !DATA_DIRECTIVES.contains(&self.directive_name().unwrap_or_default()) {
+            return Ok(data);
+        }
+        for token in &self.directive_operands {
+            match (self.directive_name(), &token.token_kind) {
+                (Some("asciiz"), TokenKind::StringOperand { value }) => {
+                    data.extend_from_slice(value.as_bytes());
+                    data.push(0);
+                }
+                (Some("byte"), TokenKind::IntegerOperand { value }) => match value {
+                    -128..=255 => data.push(*value as u8),
+                    _ => {
+                        return Err(ParseError {
+                            message: format!("{} does not fit in a byte", value),
+                            token: token.clone(),
+                        })
+                    }
+                },
+                (Some("word"), TokenKind::IntegerOperand { value }) => {
+                    data.extend_from_slice(&value.to_be_bytes())
+                }
+                (Some("double"), TokenKind::FloatOperand { value }) => {
+                    data.extend_from_slice(&value.to_be_bytes())
+                }
+                (Some("double"), TokenKind::IntegerOperand { value }) => {
+                    data.extend_from_slice(&(*value as f64).to_be_bytes())
+                }
+                (Some("space"), TokenKind::IntegerOperand { value }) if *value >= 0 => {
+                    data = padding(*value as usize, offset, token)?
+                }
+                (Some("align"), TokenKind::IntegerOperand { value }) if *value > 0 => {
+                    let alignment: usize = *value as usize;
+                    data = padding((alignment - offset % alignment) % alignment, offset, token)?
+                }
+                _ => {
+                    return Err(ParseError {
+                        message: format!(
+                            "invalid operand for .{}",
+                            self.directive_name().unwrap_or_default()
+                        ),
+                        token: token.clone(),
+                    })
+                }
+            }
         }
+        Ok(data)
+    }
+
+    /// name of the label declared by this line
+    pub fn label_name(&self) -> Option<&str> {
+        match &self.label {
+            Some(Token {
+                token_kind: TokenKind::LabelDeclaration { name },
+                ..
+            }) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn label_token(&self) -> Option<&Token> {
+        self.label.as_ref()
     }
 
-    pub fn as_bytes(&self) -> Vec<u8> {
+    /// number of bytes the line takes in the assembled program
+    pub fn byte_len(&self) -> usize {
+        match self.opcode {
+            Some(_) => INSTRUCTION_WIDTH,
+            None => 0,
+        }
+    }
+
+    /// encode as one fixed-width instruction: opcode, operands in order
+    /// (registers on 8 bits, integers and label addresses on 16 bits big endian,
+    /// heap addresses as a base register and an 8-bit offset)
+    /// then zero padding, `address` is the one of the instruction in the code section
+    /// from which branch offsets are computed
+    pub fn as_bytes(&self, symbols: &SymbolTable, address: usize) -> Result<Vec<u8>, ParseError> {
         let mut instruction_as_bytes: Vec<u8> = Vec::new();
-        match self.opcode.token_kind {
+        let opcode: &Token = match &self.opcode {
+            Some(opcode) => opcode,
+            None => return Ok(instruction_as_bytes),
+        };
+        // LOAD sign-extends its immediate, the other opcodes read it as unsigned
+        let immediate_range: RangeInclusive<i32> = match opcode.token_kind {
             TokenKind::Operation { code } => {
-                instruction_as_bytes.push(code as u8)
-            },
+                instruction_as_bytes.push(code as u8);
+                match code {
+                    Opcode::LOAD => i16::MIN as i32..=i16::MAX as i32,
+                    _ => 0..=u16::MAX as i32,
+                }
+            }
             _ => {
-                println!("[FATAL] Cannot put a non-opcode token in opcode position");
-                std::process::exit(-1);
-            }
-        }
-
-        for token in &[&self.operand_1, &self.operand_1, &self.operand_1] {
-            match token {
-                Some(t) => {
-                    // extract Operand
-                    match &t.token_kind {
-                        TokenKind::IntegerOperand { value } => {
-                            // can take up to 16 bits
-                            let buffer: u16 =  *value as u16;
-                            let byte_1: u16 = buffer;                
-                            let byte_2: u16 = buffer >> 8;        
-                            instruction_as_bytes.push(byte_2 as u8);
-                            instruction_as_bytes.push(byte_1 as u8);
+                return Err(ParseError {
+                    message: "expected an opcode".into(),
+                    token: opcode.clone(),
+                })
+            }
+        };
+        if self.is_branch() {
+            if let Some(token) = &self.operand_1 {
+                let offset: i16 = branch_offset(token, symbols, address)?;
+                instruction_as_bytes.extend_from_slice(&offset.to_be_bytes());
+            }
+            instruction_as_bytes.resize(INSTRUCTION_WIDTH, 0);
+            return Ok(instruction_as_bytes);
+        }
+
+        for token in [&self.operand_1, &self.operand_2, &self.operand_3]
+            .into_iter()
+            .flatten()
+        {
+            // extract Operand
+            match &token.token_kind {
+                TokenKind::IntegerOperand { value } => {
+                    // 16 bits, signed for LOAD and unsigned otherwise
+                    let buffer: u16 = match value {
+                        value if immediate_range.contains(value) => *value as u16,
+                        _ => {
+                            return Err(ParseError {
+                                message: format!(
+                                    "{} does not fit in the 16-bit immediate field",
+                                    value
+                                ),
+                                token: token.clone(),
+                            })
                         }
-                        TokenKind::Register { reg_index } => {
-                            instruction_as_bytes.push(*reg_index as u8);
+                    };
+                    let byte_1: u16 = buffer;
+                    let byte_2: u16 = buffer >> 8;
+                    instruction_as_bytes.push(byte_2 as u8);
+                    instruction_as_bytes.push(byte_1 as u8);
+                }
+                TokenKind::Register { reg_index } | TokenKind::FloatRegister { reg_index } => {
+                    instruction_as_bytes.push(*reg_index as u8);
+                }
+                TokenKind::MemoryOperand { base, offset } => {
+                    let offset: u8 = match u8::try_from(*offset) {
+                        Ok(offset) => offset,
+                        Err(_) => {
+                            return Err(ParseError {
+                                message: format!(
+                                    "heap offset {} must be between 0 and 255",
+                                    offset
+                                ),
+                                token: token.clone(),
+                            })
+                        }
+                    };
+                    instruction_as_bytes.push(*base as u8);
+                    instruction_as_bytes.push(offset);
+                }
+                TokenKind::LabelUsage { name } => {
+                    let offset: usize = match symbols.symbol_offset(name) {
+                        Some(offset) => offset,
+                        None => {
+                            return Err(ParseError {
+                                message: format!("undefined label '{}'", name),
+                                token: token.clone(),
+                            })
                         }
+                    };
+                    let address: u16 = match u16::try_from(offset) {
+                        Ok(address) if immediate_range.contains(&(address as i32)) => address,
                         _ => {
-                            println!("[FATAL] Only Register and IntegerOperand token kinds are accepted as operand");
-                            std::process::exit(-1);
+                            return Err(ParseError {
+                                message: format!(
+                                    "address of label '{}' does not fit in 16 bits",
+                                    name
+                                ),
+                                token: token.clone(),
+                            })
                         }
-                    }
-                },
-                None => {} // No token => no byte pushed
+                    };
+                    instruction_as_bytes.extend_from_slice(&address.to_be_bytes());
+                }
+                _ => {
+                    return Err(ParseError {
+                        message: "invalid instruction operand".into(),
+                        token: token.clone(),
+                    })
+                }
             }
         }
-        instruction_as_bytes
+        instruction_as_bytes.resize(INSTRUCTION_WIDTH, 0);
+        Ok(instruction_as_bytes)
     }
 }
 
@@ -75,42 +366,352 @@ impl Parser {
         Self { tokens_to_parse }
     }
 
-    pub fn default() -> Self {
-        Self { tokens_to_parse: Vec::new() }
+    /// build one instruction per opcode token, reading as many operands
+    /// as the opcode signature expects, and one entry per label declaration
+    /// after an error the rest of the statement is skipped and parsing goes on,
+    /// so that every error of the source is reported
+    pub fn parse(&mut self) -> Result<Vec<AssemblyInstruction>, Vec<ParseError>> {
+        let mut parsed_instructions: Vec<AssemblyInstruction> = Vec::new();
+        let mut errors: Vec<ParseError> = Vec::new();
+        let mut iterator = self.tokens_to_parse.iter().peekable();
+        while let Some(t) = iterator.next() {
+            match self.parse_statement(t, &mut iterator) {
+                Ok(Some(instruction)) => parsed_instructions.push(instruction),
+                Ok(None) => {}
+                Err(err) => {
+                    errors.push(err);
+                    while iterator.next_if(|next| is_operand(next, t)).is_some() {}
+                }
+            }
+        }
+        if errors.is_empty() {
+            pool_float_literals(&mut parsed_instructions);
+            Ok(parsed_instructions)
+        } else {
+            Err(errors)
+        }
     }
 
-    pub fn set_tokens(&mut self, tokens: Vec<Token>) {
-        self.tokens_to_parse = tokens;
-    }
+    /// parse the statement starting at `t` and the operands that follow it
+    fn parse_statement<'a>(
+        &self,
+        t: &'a Token,
+        iterator: &mut Peekable<Iter<'a, Token>>,
+    ) -> Result<Option<AssemblyInstruction>, ParseError> {
+        let (opcode, operand_kinds, mnemonic): (Token, &[OperandKind], String) = match &t.token_kind
+        {
+            TokenKind::Operation { code } => {
+                (t.clone(), code.operand_kinds(), format!("{:?}", code))
+            }
+            TokenKind::PseudoOperation { name } => match name.as_str() {
+                // LA $reg @label loads the address of a label into a register
+                "LA" => {
+                    let mut opcode: Token = t.clone();
+                    opcode.token_kind = TokenKind::Operation { code: Opcode::LOAD };
+                    let operand_kinds: &[OperandKind] =
+                        &[OperandKind::Register, OperandKind::Label];
+                    (opcode, operand_kinds, name.clone())
+                }
+                // LOADF %freg #3.14 loads a float literal or the float at a data label
+                "LOADF" => {
+                    let mut opcode: Token = t.clone();
+                    opcode.token_kind = TokenKind::Operation {
+                        code: Opcode::LOADDF,
+                    };
+                    let operand_kinds: &[OperandKind] =
+                        &[OperandKind::FloatRegister, OperandKind::Float];
+                    (opcode, operand_kinds, name.clone())
+                }
+                _ => {
+                    let msg: String = format!("unknown pseudo-instruction {}", name);
+                    return Err(self.handle_parsing_error(msg, t));
+                }
+            },
+            TokenKind::LabelDeclaration { .. } => {
+                return Ok(Some(AssemblyInstruction::label(t.clone())));
+            }
+            TokenKind::Directive { name } => {
+                let mut directive_operands: Vec<Token> = Vec::new();
+                while let Some(next) = iterator.next_if(|next| match next.token_kind {
+                    TokenKind::Register { .. }
+                    | TokenKind::IntegerOperand { .. }
+                    | TokenKind::FloatOperand { .. }
+                    | TokenKind::LabelUsage { .. }
+                    | TokenKind::StringOperand { .. } => true,
+                    // symbol names of .global and .extern
+                    TokenKind::Identifier { .. } => next.line() == t.line(),
+                    _ => false,
+                }) {
+                    directive_operands.push(next.clone());
+                }
+                self.check_directive(name, t, &directive_operands)?;
+                return Ok(Some(AssemblyInstruction::directive(
+                    t.clone(),
+                    directive_operands,
+                )));
+            }
+            // the lexer already reported it, its operands are skipped without more errors
+            TokenKind::Invalid => {
+                while iterator.next_if(|next| is_operand(next, t)).is_some() {}
+                return Ok(None);
+            }
+            TokenKind::Eof => return Ok(None),
+            TokenKind::Identifier { name } => {
+                let msg: String = format!("unknown mnemonic '{}'", name);
+                return Err(self.handle_parsing_error(msg, t));
+            }
+            _ => return Err(self.handle_parsing_error("expected an opcode".into(), t)),
+        };
 
-    /// TODO avoid .unwrap().clone() as its not safe nor memory efficient
-    pub fn parse(&mut self) -> Vec<AssemblyInstruction> {
-        let mut parsed_instructions: Vec<AssemblyInstruction> = Vec::new();
-        let mut iterator = self.tokens_to_parse.iter();
-        while let Some(t) = iterator.next() {
-            match &t.token_kind {
-                TokenKind::Operation { code } => {
-                    match code {
-                        Opcode::LOAD => {
-                            parsed_instructions.push(AssemblyInstruction::new(
-                                t.clone(),
-                                Some(iterator.next().unwrap().clone()),
-                                Some(iterator.next().unwrap().clone()),
-                                None,
-                            ));
-                        }
-                        _ => {}
+        let mut operands: [Option<Token>; 3] = [None, None, None];
+        for (index, kind) in operand_kinds.iter().enumerate() {
+            let operand: Option<&Token> = iterator.next_if(|next| is_operand(next, t));
+            let is_expected_kind: bool = match operand {
+                Some(operand) => matches!(
+                    (kind, &operand.token_kind),
+                    (OperandKind::Register, TokenKind::Register { .. })
+                        | (OperandKind::Integer, TokenKind::IntegerOperand { .. })
+                        | (OperandKind::Integer, TokenKind::LabelUsage { .. })
+                        | (OperandKind::Label, TokenKind::LabelUsage { .. })
+                        | (OperandKind::Memory, TokenKind::MemoryOperand { .. })
+                        | (OperandKind::FloatRegister, TokenKind::FloatRegister { .. })
+                        | (OperandKind::Float, TokenKind::FloatOperand { .. })
+                        | (OperandKind::Float, TokenKind::IntegerOperand { .. })
+                        | (OperandKind::Float, TokenKind::LabelUsage { .. })
+                        | (OperandKind::Offset, TokenKind::IntegerOperand { .. })
+                        | (OperandKind::Offset, TokenKind::LabelUsage { .. })
+                        | (_, TokenKind::Invalid)
+                ),
+                None => false,
+            };
+            if !is_expected_kind {
+                let msg: String = format!(
+                    "{} expects {} operand(s), operand {} must be {}",
+                    mnemonic,
+                    operand_kinds.len(),
+                    index + 1,
+                    match kind {
+                        OperandKind::Register => "a register",
+                        OperandKind::Integer => "an integer",
+                        OperandKind::Label => "a label",
+                        OperandKind::Memory => "a heap address",
+                        OperandKind::FloatRegister => "a float register",
+                        OperandKind::Float => "a float or a data label",
+                        OperandKind::Offset => "an offset or a label",
+                    }
+                );
+                // a missing operand is reported on the token that took its place,
+                // or on the instruction when it is the last of an included file
+                let next: Option<&Token> = iterator
+                    .peek()
+                    .copied()
+                    .filter(|next| next.file() == t.file());
+                let token: &Token = operand.or(next).unwrap_or(t);
+                return Err(self.handle_parsing_error(msg, token));
+            }
+            operands[index] = operand.cloned().map(|mut operand| {
+                // integer literals of LOADF are floats too
+                if let (OperandKind::Float, TokenKind::IntegerOperand { value }) =
+                    (kind, &operand.token_kind)
+                {
+                    operand.token_kind = TokenKind::FloatOperand {
+                        value: *value as f64,
                     };
                 }
-                _ => {}
+                operand
+            });
+        }
+
+        if let Some(next) = iterator.peek() {
+            if is_operand(next, t) {
+                let msg: String = format!(
+                    "too many operands, {} expects {}",
+                    mnemonic,
+                    operand_kinds.len()
+                );
+                return Err(self.handle_parsing_error(msg, next));
+            }
+        }
+
+        let [operand_1, operand_2, operand_3] = operands;
+        Ok(Some(AssemblyInstruction::new(
+            opcode, operand_1, operand_2, operand_3,
+        )))
+    }
+
+    /// check the directive exists and has the expected number and kind of operands
+    fn check_directive(
+        &self,
+        name: &str,
+        directive: &Token,
+        operands: &[Token],
+    ) -> Result<(), ParseError> {
+        if LINKAGE_DIRECTIVES.contains(&name) {
+            let msg: String = format!(".{} expects one or more symbol names", name);
+            return match operands
+                .iter()
+                .find(|operand| !matches!(operand.token_kind, TokenKind::Identifier { .. }))
+            {
+                Some(operand) => Err(self.handle_parsing_error(msg, operand)),
+                None if operands.is_empty() => Err(self.handle_parsing_error(msg, directive)),
+                None => Ok(()),
+            };
+        }
+        let (min_count, max_count, expects_string, expected): (usize, usize, bool, &str) =
+            match name {
+                _ if SECTION_DIRECTIVES.contains(&name) => (0, 0, false, "no operand"),
+                "asciiz" => (1, 1, true, "one string"),
+                "space" | "align" => (1, 1, false, "one integer"),
+                "byte" | "word" => (1, usize::MAX, false, "one or more integers"),
+                "double" => (1, usize::MAX, false, "one or more numbers"),
+                _ => {
+                    let msg: String = format!("unknown directive .{}", name);
+                    return Err(self.handle_parsing_error(msg, directive));
+                }
+            };
+        let msg: String = format!(".{} expects {}", name, expected);
+        if operands.len() < min_count || operands.len() > max_count {
+            let token: &Token = operands.get(max_count).unwrap_or(directive);
+            return Err(self.handle_parsing_error(msg, token));
+        }
+        for operand in operands {
+            let is_expected_kind: bool = match operand.token_kind {
+                TokenKind::StringOperand { .. } => expects_string,
+                TokenKind::IntegerOperand { .. } => !expects_string,
+                TokenKind::FloatOperand { .. } => name == "double",
+                _ => false,
+            };
+            if !is_expected_kind {
+                return Err(self.handle_parsing_error(msg, operand));
             }
         }
-        parsed_instructions
+        Ok(())
     }
 
-    fn handle_parsing_error(&self) {
-        todo!()
+    fn handle_parsing_error(&self, message: String, token: &Token) -> ParseError {
+        ParseError {
+            message,
+            token: token.clone(),
+        }
+    }
+}
+
+/// tokens that can only appear after an opcode or a directive, invalid tokens
+/// are operands of `statement` when they sit on its line
+fn is_operand(token: &Token, statement: &Token) -> bool {
+    match token.token_kind {
+        TokenKind::Register { .. }
+        | TokenKind::IntegerOperand { .. }
+        | TokenKind::FloatRegister { .. }
+        | TokenKind::FloatOperand { .. }
+        | TokenKind::LabelUsage { .. }
+        | TokenKind::StringOperand { .. }
+        | TokenKind::MemoryOperand { .. } => true,
+        TokenKind::Invalid => token.line() == statement.line(),
+        _ => false,
+    }
+}
+
+/// `len` zero bytes of `.space` or `.align` placed at data `offset`, data addresses
+/// are 16 bits so the size is checked before allocating
+fn padding(len: usize, offset: usize, token: &Token) -> Result<Vec<u8>, ParseError> {
+    if offset + len > u16::MAX as usize {
+        return Err(ParseError {
+            message: format!(
+                "{} bytes at {:#06X} go past the 16-bit data addresses",
+                len, offset
+            ),
+            token: token.clone(),
+        });
     }
+    Ok(vec![0; len])
+}
+
+/// offset of a branch at `address` to its label, or its integer operand
+/// counted from the next instruction
+fn branch_offset(token: &Token, symbols: &SymbolTable, address: usize) -> Result<i16, ParseError> {
+    let (offset, message): (i64, String) = match &token.token_kind {
+        TokenKind::IntegerOperand { value } => (
+            *value as i64,
+            format!("branch offset {} does not fit in 16 bits", value),
+        ),
+        TokenKind::LabelUsage { name } => {
+            match symbols.iter().find(|symbol| symbol.name == *name) {
+                Some(symbol) if symbol.section == Section::Data => {
+                    return Err(ParseError {
+                        message: format!(
+                            "branches only reach code labels, '{}' is in the .data section",
+                            name
+                        ),
+                        token: token.clone(),
+                    })
+                }
+                Some(symbol) => (
+                    symbol.offset as i64 - (address + INSTRUCTION_WIDTH) as i64,
+                    format!("label '{}' is too far for a branch", name),
+                ),
+                None => {
+                    return Err(ParseError {
+                        message: format!("undefined label '{}'", name),
+                        token: token.clone(),
+                    })
+                }
+            }
+        }
+        _ => {
+            return Err(ParseError {
+                message: "invalid instruction operand".into(),
+                token: token.clone(),
+            })
+        }
+    };
+    i16::try_from(offset).map_err(|_| ParseError {
+        message,
+        token: token.clone(),
+    })
+}
+
+/// replace every float literal operand by a reference to a `.double` holding it,
+/// placed at the end of the data section under a label no source label can have
+fn pool_float_literals(instructions: &mut Vec<AssemblyInstruction>) {
+    let mut pool: Vec<AssemblyInstruction> = Vec::new();
+    for instruction in instructions.iter_mut() {
+        for operand in [
+            &mut instruction.operand_1,
+            &mut instruction.operand_2,
+            &mut instruction.operand_3,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if !matches!(operand.token_kind, TokenKind::FloatOperand { .. }) {
+                continue;
+            }
+            let name: String = format!("float.{}", pool.len() / 3);
+            let with_kind = |token_kind: TokenKind| -> Token {
+                let mut token: Token = operand.clone();
+                token.token_kind = token_kind;
+                token
+            };
+            pool.push(AssemblyInstruction::directive(
+                with_kind(TokenKind::Directive {
+                    name: "data".into(),
+                }),
+                Vec::new(),
+            ));
+            pool.push(AssemblyInstruction::label(with_kind(
+                TokenKind::LabelDeclaration { name: name.clone() },
+            )));
+            pool.push(AssemblyInstruction::directive(
+                with_kind(TokenKind::Directive {
+                    name: "double".into(),
+                }),
+                vec![operand.clone()],
+            ));
+            operand.token_kind = TokenKind::LabelUsage { name };
+        }
+    }
+    instructions.append(&mut pool);
 }
 
 #[cfg(test)]
@@ -125,14 +726,229 @@ mod test {
         let mut lexer: Lexer = Lexer::new(content, content.len());
         lexer.tokenize();
         let mut parser: Parser = Parser::new(lexer.tokens);
-        let parsing_result: Vec<_> = parser.parse();
+        let parsing_result: Vec<_> = parser.parse().unwrap();
         assert_eq!(
             parsing_result
-                .get(0)
+                .first()
                 .unwrap()
                 .opcode
+                .as_ref()
+                .unwrap()
                 .token_kind,
             TokenKind::Operation { code: Opcode::LOAD }
         )
     }
+
+    fn parse_source(content: &str) -> Result<Vec<AssemblyInstruction>, ParseError> {
+        parse_all(content).map_err(|mut errors| errors.remove(0))
+    }
+
+    fn parse_all(content: &str) -> Result<Vec<AssemblyInstruction>, Vec<ParseError>> {
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        let mut parser: Parser = Parser::new(lexer.tokens);
+        parser.parse()
+    }
+
+    #[test]
+    fn parse_every_signature() {
+        let content: &str = "LOAD $1 #500\nADD $0 $1 $2\nEQ $0 $1\nJMP $3\nHLT\nRSHTI $4 #2\n";
+        let parsing_result: Vec<_> = parse_source(content).unwrap();
+        let opcodes: Vec<_> = parsing_result
+            .iter()
+            .map(|instruction| instruction.opcode.as_ref().unwrap().token_kind.clone())
+            .collect();
+        assert_eq!(
+            opcodes,
+            vec![
+                TokenKind::Operation { code: Opcode::LOAD },
+                TokenKind::Operation { code: Opcode::ADD },
+                TokenKind::Operation { code: Opcode::EQ },
+                TokenKind::Operation { code: Opcode::JMP },
+                TokenKind::Operation { code: Opcode::HLT },
+                TokenKind::Operation {
+                    code: Opcode::RSHTI
+                },
+            ]
+        );
+        let add: &AssemblyInstruction = &parsing_result[1];
+        assert_eq!(
+            add.operand_3.as_ref().unwrap().token_kind,
+            TokenKind::Register { reg_index: 2 }
+        );
+        assert_eq!(parsing_result[3].operand_2, None)
+    }
+
+    #[test]
+    fn reject_wrong_operand_kind() {
+        let result = parse_source("ADD $0 #1 $2");
+        assert_eq!(
+            result.unwrap_err().token.token_kind,
+            TokenKind::IntegerOperand { value: 1 }
+        )
+    }
+
+    #[test]
+    fn reject_missing_operand() {
+        let result = parse_source("LOAD $1\nHLT");
+        assert_eq!(
+            result.unwrap_err().token.token_kind,
+            TokenKind::Operation { code: Opcode::HLT }
+        );
+        let result = parse_source("ADD $0 $1");
+        assert_eq!(result.unwrap_err().token.token_kind, TokenKind::Eof)
+    }
+
+    #[test]
+    fn reject_extra_operand() {
+        let result = parse_source("INC $0 $1");
+        assert_eq!(
+            result.unwrap_err().token.token_kind,
+            TokenKind::Register { reg_index: 1 }
+        )
+    }
+
+    #[test]
+    fn parse_labels() {
+        let parsing_result: Vec<_> = parse_source("start: LA $1 @start\nend:").unwrap();
+        assert_eq!(parsing_result.len(), 3);
+        assert_eq!(parsing_result[0].label_name(), Some("start"));
+        assert_eq!(
+            parsing_result[1].opcode.as_ref().unwrap().token_kind,
+            TokenKind::Operation { code: Opcode::LOAD }
+        );
+        assert_eq!(parsing_result[2].label_name(), Some("end"));
+        assert_eq!(parsing_result[2].byte_len(), 0)
+    }
+
+    #[test]
+    fn la_expects_a_label() {
+        let result = parse_source("LA $1 #12");
+        assert_eq!(
+            result.unwrap_err().token.token_kind,
+            TokenKind::IntegerOperand { value: 12 }
+        )
+    }
+
+    #[test]
+    fn parse_directives() {
+        let content: &str = ".data\nmsg: .asciiz \"hi\"\ntable: .word #1 #2 #3\n.code\nHLT";
+        let parsing_result: Vec<_> = parse_source(content).unwrap();
+        assert_eq!(parsing_result.len(), 7);
+        assert_eq!(parsing_result[0].directive_name(), Some("data"));
+        assert_eq!(parsing_result[2].data_bytes(0).unwrap(), b"hi\0");
+        assert_eq!(
+            parsing_result[4].data_bytes(3).unwrap(),
+            [0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3]
+        )
+    }
+
+    #[test]
+    fn align_and_space() {
+        let parsing_result: Vec<_> = parse_source(".align #4\n.space #3\n.byte #-1 #255").unwrap();
+        assert_eq!(parsing_result[0].data_bytes(5).unwrap(), [0, 0, 0]);
+        assert_eq!(parsing_result[0].data_bytes(8).unwrap(), []);
+        assert_eq!(parsing_result[1].data_bytes(0).unwrap(), [0, 0, 0]);
+        assert_eq!(parsing_result[2].data_bytes(0).unwrap(), [255, 255])
+    }
+
+    #[test]
+    fn reject_bad_directives() {
+        assert!(parse_source(".text").is_err());
+        assert!(parse_source(".asciiz #1").is_err());
+        assert!(parse_source(".word").is_err());
+        assert!(parse_source(".space #1 #2").is_err());
+        assert!(parse_source(".data #1").is_err());
+        assert!(parse_source(".byte #256").unwrap()[0]
+            .data_bytes(0)
+            .is_err());
+        assert_eq!(
+            parse_source(".space #2000000000").unwrap()[0]
+                .data_bytes(0)
+                .unwrap_err()
+                .message,
+            "2000000000 bytes at 0x0000 go past the 16-bit data addresses"
+        );
+        assert!(parse_source(".space #16").unwrap()[0]
+            .data_bytes(65520)
+            .is_err());
+        assert!(parse_source(".align #2000000000").unwrap()[0]
+            .data_bytes(1)
+            .is_err())
+    }
+
+    #[test]
+    fn parse_memory_operands() {
+        let parsing_result: Vec<_> =
+            parse_source("LOADW $1 [ $2 + #4 ]\nSTOREB [ $2 ] $1").unwrap();
+        assert_eq!(
+            parsing_result[0].operand_2.as_ref().unwrap().token_kind,
+            TokenKind::MemoryOperand { base: 2, offset: 4 }
+        );
+        let symbols: SymbolTable = SymbolTable::new();
+        assert_eq!(
+            parsing_result[0].as_bytes(&symbols, 0).unwrap(),
+            [39, 1, 2, 4]
+        );
+        assert_eq!(
+            parsing_result[1].as_bytes(&symbols, 0).unwrap(),
+            [40, 2, 0, 1]
+        );
+        assert!(parse_source("LOADW [ $2 ] $1").is_err());
+        let out_of_range: Vec<_> = parse_source("LOADB $1 [ $2 + #256 ]").unwrap();
+        assert!(out_of_range[0].as_bytes(&symbols, 0).is_err())
+    }
+
+    #[test]
+    fn recover_after_errors() {
+        let errors: Vec<ParseError> =
+            parse_all("ADD $0 #1 $2\nHLT $3\nLOAD $1\nINC $0\nLAOD $1 #2\n.word \"x\"")
+                .unwrap_err();
+        let positions: Vec<(usize, usize)> = errors
+            .iter()
+            .map(|err| (err.token.line(), err.token.column()))
+            .collect();
+        assert_eq!(positions, [(1, 8), (2, 5), (4, 1), (5, 1), (6, 7)]);
+        // the operands of an unknown mnemonic are skipped with it
+        assert_eq!(parse_all("LAOD $1 #2\nHLT").unwrap_err().len(), 1)
+    }
+
+    #[test]
+    fn encode_invalid_operand() {
+        let mut instructions: Vec<_> = parse_source(".asciiz \"hi\"").unwrap();
+        let directive: AssemblyInstruction = instructions.remove(0);
+        let instruction: AssemblyInstruction =
+            AssemblyInstruction::new(directive.directive.clone().unwrap(), None, None, None);
+        assert_eq!(
+            instruction
+                .as_bytes(&SymbolTable::new(), 0)
+                .unwrap_err()
+                .message,
+            "expected an opcode"
+        )
+    }
+
+    #[test]
+    fn immediate_overflow() {
+        let symbols: SymbolTable = SymbolTable::new();
+        let encode = |content: &str| parse_source(content).unwrap()[0].as_bytes(&symbols, 0);
+        assert_eq!(encode("LOAD $1 #32767").unwrap(), [1, 1, 127, 255]);
+        assert_eq!(encode("LOAD $1 #-32768").unwrap(), [1, 1, 128, 0]);
+        assert_eq!(encode("SYSCALL #65535").unwrap(), [42, 255, 255, 0]);
+        assert_eq!(
+            encode("LOAD $1 #0xFFFF").unwrap_err().message,
+            "65535 does not fit in the 16-bit immediate field"
+        );
+        assert_eq!(
+            encode("SYSCALL #0x10000").unwrap_err().message,
+            "65536 does not fit in the 16-bit immediate field"
+        );
+        assert!(encode("LOAD $1 #-32769").is_err());
+        assert_eq!(
+            encode("SYSCALL #-1").unwrap_err().message,
+            "-1 does not fit in the 16-bit immediate field"
+        );
+        assert!(encode("RSHTI $1 #-1").is_err());
+        assert!(encode("LOADDF %f0 #-8").is_err())
+    }
 }
@@ -1,30 +1,293 @@
-use super::parser::AssemblyInstruction;
+use crate::executable::Executable;
 
+use super::{
+    lexer::{Token, TokenKind},
+    linker::{Object, Relocation},
+    parser::{AssemblyInstruction, ParseError, DATA_DIRECTIVES},
+    symbols::{Section, Symbol, SymbolTable},
+};
+
+#[derive(Debug, Default)]
 pub struct Program {
-    instructions: Vec<AssemblyInstruction>
+    instructions: Vec<AssemblyInstruction>,
+    /// address of the first instruction, code labels and branches are relative to it
+    code_origin: usize,
+    /// address of the first data byte, data labels are relative to it
+    data_origin: usize,
+    /// labels of the code and data placed before it, that its instructions can reference
+    labels: SymbolTable,
 }
 
 impl Program {
-    pub fn default() -> Self {
-        Self { instructions: Vec::new() }
+    /// empty program whose code and data are placed at `code_origin` and `data_origin`,
+    /// after code and data assembled before it whose labels are `labels`
+    pub fn at(code_origin: usize, data_origin: usize, labels: SymbolTable) -> Self {
+        Self {
+            code_origin,
+            data_origin,
+            labels,
+            ..Self::default()
+        }
     }
 
     pub fn set_instructions(&mut self, new_instructions: Vec<AssemblyInstruction>) {
         self.instructions = new_instructions;
     }
 
-    pub fn as_bytes(&self) -> Vec<u8> {
+    /// first pass: give every label the offset of what follows it in its section
+    /// and check instructions and data directives sit in the right section
+    pub fn symbols(&self) -> Result<SymbolTable, ParseError> {
+        let mut errors: Vec<ParseError> = Vec::new();
+        let symbols: SymbolTable = self.layout(&mut errors);
+        match errors.into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(symbols),
+        }
+    }
+
+    /// every error of the program, from both passes
+    /// references to `.extern` symbols are left for the linker
+    pub fn errors(&self) -> Vec<ParseError> {
+        let mut errors: Vec<ParseError> = Vec::new();
+        let symbols: SymbolTable = self.object_symbols(&mut errors);
+        let externs: Vec<(&str, &Token)> = self.linkage_symbols("extern");
+        let mut address: usize = self.code_origin;
+        for instruction in &self.instructions {
+            match instruction.branch_label() {
+                Some((name, token)) if externs.iter().any(|(symbol, _)| *symbol == name) => errors
+                    .push(ParseError {
+                        message: format!(
+                            "branches only reach labels of their own file, '{}' is .extern",
+                            name
+                        ),
+                        token: token.clone(),
+                    }),
+                _ => {
+                    if let Err(err) = instruction.as_bytes(&symbols, address) {
+                        errors.push(err);
+                    }
+                }
+            }
+            address += instruction.byte_len();
+        }
+        errors
+    }
+
+    /// lay out the sections, collecting errors instead of stopping at the first one
+    fn layout(&self, errors: &mut Vec<ParseError>) -> SymbolTable {
+        let mut symbols: SymbolTable = SymbolTable::new();
+        let mut section: Section = Section::Code;
+        let mut code_offset: usize = self.code_origin;
+        let mut data_offset: usize = self.data_origin;
+        for instruction in &self.instructions {
+            match instruction.directive_name() {
+                Some("code") => section = Section::Code,
+                Some("data") => section = Section::Data,
+                _ => {}
+            }
+            if let (Some(name), Some(token)) = (instruction.label_name(), instruction.label_token())
+            {
+                if symbols.has_symbol(name) || self.labels.has_symbol(name) {
+                    let first_declaration: Option<&Token> = self
+                        .instructions
+                        .iter()
+                        .find(|other| other.label_name() == Some(name))
+                        .and_then(|other| other.label_token());
+                    let message: String = match first_declaration {
+                        Some(first) => format!(
+                            "duplicate label '{}', first declared at {}:{}",
+                            name,
+                            first.line(),
+                            first.column()
+                        ),
+                        None => format!("duplicate label '{}'", name),
+                    };
+                    errors.push(ParseError {
+                        message,
+                        token: token.clone(),
+                    });
+                } else {
+                    let offset: usize = match section {
+                        Section::Code => code_offset,
+                        Section::Data => data_offset,
+                    };
+                    symbols.add_symbol(Symbol::new(name, offset, section));
+                }
+            }
+            if let Some(opcode) = instruction.opcode_token() {
+                if section != Section::Code {
+                    errors.push(ParseError {
+                        message: "instructions must be placed in the .code section".into(),
+                        token: opcode.clone(),
+                    });
+                }
+                code_offset += instruction.byte_len();
+            }
+            if let Some(directive) = instruction.directive_token() {
+                if DATA_DIRECTIVES.contains(&instruction.directive_name().unwrap_or_default()) {
+                    if section != Section::Data {
+                        errors.push(ParseError {
+                            message: "data directives must be placed in the .data section".into(),
+                            token: directive.clone(),
+                        });
+                    }
+                    match instruction.data_bytes(data_offset) {
+                        Ok(data) => data_offset += data.len(),
+                        Err(err) => errors.push(err),
+                    }
+                }
+            }
+        }
+        for (name, token) in self.linkage_symbols("global") {
+            if !symbols.has_symbol(name) {
+                errors.push(ParseError {
+                    message: format!("global symbol '{}' is not defined", name),
+                    token: token.clone(),
+                });
+            }
+        }
+        for (name, token) in self.linkage_symbols("extern") {
+            if symbols.has_symbol(name) {
+                errors.push(ParseError {
+                    message: format!("'{}' is declared .extern but defined in this file", name),
+                    token: token.clone(),
+                });
+            }
+        }
+        for label in self.labels.iter() {
+            symbols.add_symbol(label.clone());
+        }
+        symbols
+    }
+
+    /// symbols given to every `.global` or `.extern` directive, with their token
+    fn linkage_symbols(&self, directive: &str) -> Vec<(&str, &Token)> {
+        self.instructions
+            .iter()
+            .filter(|instruction| instruction.directive_name() == Some(directive))
+            .flat_map(|instruction| instruction.symbol_names())
+            .collect()
+    }
+
+    /// symbols of the program and `.extern` symbols at address 0, to encode
+    /// instructions whose label references the linker patches
+    fn object_symbols(&self, errors: &mut Vec<ParseError>) -> SymbolTable {
+        let mut symbols: SymbolTable = self.layout(errors);
+        for (name, _) in self.linkage_symbols("extern") {
+            if !symbols.has_symbol(name) {
+                symbols.add_symbol(Symbol::new(name, 0, Section::Code));
+            }
+        }
+        symbols
+    }
+
+    /// second pass: encode every instruction with label references resolved
+    pub fn as_bytes(&self) -> Result<Vec<u8>, ParseError> {
+        let symbols: SymbolTable = self.symbols()?;
         let mut byte_instructions: Vec<u8> = Vec::new();
         for instruction in &self.instructions {
-            byte_instructions.append(&mut instruction.as_bytes());
+            let address: usize = self.code_origin + byte_instructions.len();
+            byte_instructions.append(&mut instruction.as_bytes(&symbols, address)?);
+        }
+        Ok(byte_instructions)
+    }
+
+    /// entry point of the program, the `main` label if there is one in the code section
+    pub fn entry_point(&self) -> Result<usize, ParseError> {
+        let symbols: SymbolTable = self.symbols()?;
+        let entry_point: Option<usize> = symbols
+            .iter()
+            .find(|symbol| symbol.name == "main" && symbol.section == Section::Code)
+            .map(|symbol| symbol.offset);
+        Ok(entry_point.unwrap_or(0))
+    }
+
+    /// source line of every instruction, by code offset
+    pub fn line_table(&self) -> Vec<(usize, usize)> {
+        let mut line_table: Vec<(usize, usize)> = Vec::new();
+        let mut offset: usize = 0;
+        for instruction in &self.instructions {
+            if let Some(opcode) = instruction.opcode_token() {
+                line_table.push((offset, opcode.line()));
+                offset += instruction.byte_len();
+            }
+        }
+        line_table
+    }
+
+    pub fn to_executable(&self) -> Result<Executable, ParseError> {
+        if let Some((name, token)) = self.linkage_symbols("extern").first() {
+            return Err(ParseError {
+                message: format!(
+                    "'{}' is declared .extern, link with the file that defines it",
+                    name
+                ),
+                token: (*token).clone(),
+            });
         }
-        byte_instructions
+        Ok(Executable {
+            entry_point: self.entry_point()?,
+            code: self.as_bytes()?,
+            ro_data: self.data_bytes()?,
+            symbols: self.symbols()?,
+            line_table: self.line_table(),
+        })
+    }
+
+    /// relocatable object of the program, `name` identifies it in link errors
+    pub fn to_object(&self, name: &str) -> Result<Object, ParseError> {
+        let mut errors: Vec<ParseError> = Vec::new();
+        let symbols: SymbolTable = self.object_symbols(&mut errors);
+        if let Some(err) = errors.into_iter().next() {
+            return Err(err);
+        }
+        let mut code: Vec<u8> = Vec::new();
+        let mut relocations: Vec<Relocation> = Vec::new();
+        for instruction in &self.instructions {
+            for (position, token) in instruction.label_operands() {
+                if let TokenKind::LabelUsage { name } = &token.token_kind {
+                    relocations.push(Relocation {
+                        offset: code.len() + position,
+                        symbol: name.clone(),
+                    });
+                }
+            }
+            let address: usize = self.code_origin + code.len();
+            code.append(&mut instruction.as_bytes(&symbols, address)?);
+        }
+        let to_names = |symbols: Vec<(&str, &Token)>| -> Vec<String> {
+            symbols.into_iter().map(|(name, _)| name.into()).collect()
+        };
+        Ok(Object {
+            name: name.into(),
+            code,
+            ro_data: self.data_bytes()?,
+            symbols: self.symbols()?,
+            globals: to_names(self.linkage_symbols("global")),
+            externs: to_names(self.linkage_symbols("extern")),
+            relocations,
+            line_table: self.line_table(),
+        })
+    }
+
+    /// read-only data segment laid out by the data directives
+    pub fn data_bytes(&self) -> Result<Vec<u8>, ParseError> {
+        self.symbols()?;
+        let mut data: Vec<u8> = Vec::new();
+        for instruction in &self.instructions {
+            data.append(&mut instruction.data_bytes(self.data_origin + data.len())?);
+        }
+        Ok(data)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{assembler::{lexer::Lexer, parser::Parser}, vm::VM};
+    use crate::{
+        assembler::{lexer::Lexer, parser::Parser},
+        instruction::Opcode,
+        vm::{error::ExitReason, VM},
+    };
 
     use super::*;
 
@@ -34,9 +297,12 @@ mod test {
         let mut lexer: Lexer = Lexer::new(content, content.len());
         lexer.tokenize();
         let mut parser: Parser = Parser::new(lexer.tokens);
-        let parsing_result: Vec<_> = parser.parse();
-        let program: Program = Program { instructions: parsing_result };
-        let program_as_bytes: Vec<u8> = program.as_bytes();
+        let parsing_result: Vec<_> = parser.parse().unwrap();
+        let program: Program = Program {
+            instructions: parsing_result,
+            ..Program::default()
+        };
+        let program_as_bytes: Vec<u8> = program.as_bytes().unwrap();
         assert_eq!(program.instructions.len(), 2);
         assert_eq!(program_as_bytes.len(), 8)
     }
@@ -47,13 +313,369 @@ mod test {
         let mut lexer: Lexer = Lexer::new(content, content.len());
         lexer.tokenize();
         let mut parser: Parser = Parser::new(lexer.tokens);
-        let parsing_result: Vec<_> = parser.parse();
-        let program: Program = Program { instructions: parsing_result };
-        let program_as_bytes: Vec<u8> = program.as_bytes();
+        let parsing_result: Vec<_> = parser.parse().unwrap();
+        let program: Program = Program {
+            instructions: parsing_result,
+            ..Program::default()
+        };
+        let program_as_bytes: Vec<u8> = program.as_bytes().unwrap();
         let mut vm: VM = VM::new();
         vm.bytecode = program_as_bytes;
-        vm.run();
+        vm.run().unwrap();
         println!("[DEBUG] Vm registers state {:#?}", vm.registers);
         assert_eq!(vm.registers[1], 500)
     }
+
+    fn assemble(content: &str) -> Vec<u8> {
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        let mut parser: Parser = Parser::new(lexer.tokens);
+        let program: Program = Program {
+            instructions: parser.parse().unwrap(),
+            ..Program::default()
+        };
+        program.as_bytes().unwrap()
+    }
+
+    #[test]
+    fn encode_every_opcode() {
+        let cases: Vec<(&str, [u8; 4])> = vec![
+            ("HLT", [0, 0, 0, 0]),
+            ("LOAD $1 #500", [1, 1, 1, 244]),
+            ("ADD $0 $1 $2", [2, 0, 1, 2]),
+            ("SUB $0 $1 $2", [3, 0, 1, 2]),
+            ("MUL $0 $1 $2", [4, 0, 1, 2]),
+            ("DIV $0 $1 $2", [5, 0, 1, 2]),
+            ("EQ $3 $4", [6, 3, 4, 0]),
+            ("NEQ $3 $4", [7, 3, 4, 0]),
+            ("GT $3 $4", [8, 3, 4, 0]),
+            ("GEQ $3 $4", [9, 3, 4, 0]),
+            ("LE $3 $4", [10, 3, 4, 0]),
+            ("LEQ $3 $4", [11, 3, 4, 0]),
+            ("JEQ $5", [12, 5, 0, 0]),
+            ("JNEQ $5", [13, 5, 0, 0]),
+            ("JMP $5", [14, 5, 0, 0]),
+            ("JMPF $5", [15, 5, 0, 0]),
+            ("JMPB $5", [16, 5, 0, 0]),
+            ("INC $6", [17, 6, 0, 0]),
+            ("DEC $6", [18, 6, 0, 0]),
+            ("ALOC $7", [19, 7, 0, 0]),
+            ("RSHT $0 $1 $2", [20, 0, 1, 2]),
+            ("LFST $0 $1 $2", [21, 0, 1, 2]),
+            ("RROR $0 $1 $2", [22, 0, 1, 2]),
+            ("LROR $0 $1 $2", [23, 0, 1, 2]),
+            ("AND $0 $1 $2", [24, 0, 1, 2]),
+            ("OR $0 $1 $2", [25, 0, 1, 2]),
+            ("XOR $0 $1 $2", [26, 0, 1, 2]),
+            ("NOT $0 $1", [27, 0, 1, 0]),
+            ("RSHTI $8 #3", [28, 8, 0, 3]),
+            ("LFSTI $8 #3", [29, 8, 0, 3]),
+            ("RRORI $8 #3", [30, 8, 0, 3]),
+            ("LRORI $8 #258", [31, 8, 1, 2]),
+            ("SYSCALL #2", [42, 0, 2, 0]),
+            ("ADDO $0 $1 $2", [43, 0, 1, 2]),
+            ("SUBO $0 $1 $2", [44, 0, 1, 2]),
+            ("MULO $0 $1 $2", [45, 0, 1, 2]),
+            ("JZ $5", [46, 5, 0, 0]),
+            ("JNZ $5", [47, 5, 0, 0]),
+            ("JC $5", [48, 5, 0, 0]),
+            ("JNC $5", [49, 5, 0, 0]),
+            ("JO $5", [50, 5, 0, 0]),
+            ("JNO $5", [51, 5, 0, 0]),
+            ("LOADDF %f1 #16", [52, 1, 0, 16]),
+            ("ADDF %f0 %f1 %f2", [53, 0, 1, 2]),
+            ("SUBF %f0 %f1 %f2", [54, 0, 1, 2]),
+            ("MULF %f0 %f1 %f2", [55, 0, 1, 2]),
+            ("DIVF %f0 %f1 %f2", [56, 0, 1, 2]),
+            ("EQF %f3 %f4", [57, 3, 4, 0]),
+            ("NEQF %f3 %f4", [58, 3, 4, 0]),
+            ("GTF %f3 %f4", [59, 3, 4, 0]),
+            ("GEQF %f3 %f4", [60, 3, 4, 0]),
+            ("LEF %f3 %f4", [61, 3, 4, 0]),
+            ("LEQF %f3 %f4", [62, 3, 4, 0]),
+            ("ITOF %f1 $2", [63, 1, 2, 0]),
+            ("FTOI $1 %f2", [64, 1, 2, 0]),
+            ("CMP $3 $4", [65, 3, 4, 0]),
+            ("JLT $5", [66, 5, 0, 0]),
+            ("JGT $5", [67, 5, 0, 0]),
+            ("JLE $5", [68, 5, 0, 0]),
+            ("JGE $5", [69, 5, 0, 0]),
+            ("BZ #8", [70, 0, 8, 0]),
+            ("BNZ #-8", [71, 255, 248, 0]),
+            ("BLT #4", [72, 0, 4, 0]),
+            ("BGT #4", [73, 0, 4, 0]),
+            ("BLE #4", [74, 0, 4, 0]),
+            ("BGE #4", [75, 0, 4, 0]),
+            ("CMOVZ $1 $2", [76, 1, 2, 0]),
+            ("CMOVNZ $1 $2", [77, 1, 2, 0]),
+            ("CMOVLT $1 $2", [78, 1, 2, 0]),
+            ("CMOVGT $1 $2", [79, 1, 2, 0]),
+            ("CMOVLE $1 $2", [80, 1, 2, 0]),
+            ("CMOVGE $1 $2", [81, 1, 2, 0]),
+        ];
+        for (content, expected) in cases {
+            let program_as_bytes: Vec<u8> = assemble(content);
+            assert_eq!(program_as_bytes, expected, "{}", content);
+            assert_eq!(
+                Opcode::from(program_as_bytes[0]),
+                Opcode::from(content.split(' ').next().unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let content: &str = "LOAD $0 #12\nLOAD $1 #5\nADD $0 $1 $2\nSUB $2 $1 $3\n\
+            MUL $1 $1 $4\nDIV $4 $0 $5\nINC $5\nDEC $3\nLOAD $6 #2\nLFST $1 $6 $7\n\
+            RSHTI $7 #1\nRROR $1 $6 $8\nLROR $8 $6 $8\nAND $0 $1 $9\nOR $0 $1 $10\n\
+            XOR $0 $1 $11\nNOT $11 $12\nLFSTI $12 #1\nRRORI $12 #1\nLRORI $12 #1\n\
+            ALOC $6\nEQ $0 $1\nNEQ $0 $1\nLOAD $13 #100\nJEQ $13\nGT $0 $1\n\
+            GEQ $0 $1\nLE $0 $1\nLEQ $1 $0\nLOAD $14 #132\nJNEQ $14\nJEQ $14\n\
+            HLT\nLOAD $15 #4\nJMPF $15\nHLT\nLOAD $15 #12\nJMPB $15\nHLT\nHLT";
+        let program_as_bytes: Vec<u8> = assemble(content);
+        assert_eq!(program_as_bytes.len(), 40 * 4);
+        let mut vm: VM = VM::new();
+        vm.bytecode = program_as_bytes;
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(vm.registers[2], 17);
+        assert_eq!(vm.registers[3], 11);
+        assert_eq!(vm.registers[4], 25);
+        assert_eq!(vm.registers[5], 3);
+        assert_eq!(vm.div_remainder, 1);
+        assert_eq!(vm.registers[7], 10);
+        assert_eq!(vm.registers[8], 5);
+        assert_eq!(vm.registers[9], 12 & 5);
+        assert_eq!(vm.registers[10], 12 | 5);
+        assert_eq!(vm.registers[11], 12 ^ 5);
+        assert_eq!(vm.registers[12], !(12 ^ 5) << 1);
+        assert_eq!(vm.heap.len(), 2);
+        assert!(vm.flags.zero);
+        // JEQ skips the first HLT, JMPF skips the second and JMPB lands back on it
+        assert_eq!(vm.program_counter, 36 * 4)
+    }
+
+    fn assemble_source(content: &str) -> Result<Vec<u8>, ParseError> {
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        let mut parser: Parser = Parser::new(lexer.tokens);
+        let instructions: Vec<AssemblyInstruction> =
+            parser.parse().map_err(|mut errors| errors.remove(0))?;
+        let program: Program = Program {
+            instructions,
+            ..Program::default()
+        };
+        program.as_bytes()
+    }
+
+    #[test]
+    fn resolve_labels() {
+        // counts $0 up to 5 with a backward jump to a label declared before its use
+        let content: &str = "LOAD $1 #5\nLA $2 @end\nLA $3 @loop\n\
+            loop: INC $0\nEQ $0 $1\nJEQ $2\nJMP $3\nend: HLT";
+        let program_as_bytes: Vec<u8> = assemble_source(content).unwrap();
+        assert_eq!(program_as_bytes[4..8], [1, 2, 0, 28]);
+        assert_eq!(program_as_bytes[8..12], [1, 3, 0, 12]);
+        let mut vm: VM = VM::new();
+        vm.bytecode = program_as_bytes;
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(vm.registers[0], 5)
+    }
+
+    #[test]
+    fn label_as_integer_operand() {
+        let program_as_bytes: Vec<u8> = assemble_source("HLT\nhere: LOAD $0 @here").unwrap();
+        assert_eq!(program_as_bytes[4..], [1, 0, 0, 4])
+    }
+
+    #[test]
+    fn undefined_label() {
+        let err: ParseError = assemble_source("LOAD $0 #1\n  LA $1 @nowhere").unwrap_err();
+        assert_eq!((err.token.line(), err.token.column()), (2, 9));
+        assert!(err.message.contains("nowhere"))
+    }
+
+    #[test]
+    fn duplicate_label() {
+        let err: ParseError = assemble_source("twice: HLT\ntwice: HLT").unwrap_err();
+        assert_eq!((err.token.line(), err.token.column()), (2, 1));
+        assert!(err.message.contains("1:1"))
+    }
+
+    #[test]
+    fn data_section_layout() {
+        let content: &str = ".data\nmsg: .asciiz \"abc\"\n.align #4\ntable: .word #7 #-1\n\
+            .code\nLA $0 @table\nLA $1 @msg\nHLT";
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        let mut parser: Parser = Parser::new(lexer.tokens);
+        let program: Program = Program {
+            instructions: parser.parse().unwrap(),
+            ..Program::default()
+        };
+        let symbols: SymbolTable = program.symbols().unwrap();
+        assert_eq!(symbols.symbol_offset("table"), Some(4));
+        assert_eq!(
+            program.data_bytes().unwrap(),
+            [97, 98, 99, 0, 0, 0, 0, 7, 255, 255, 255, 255]
+        );
+        assert_eq!(program.as_bytes().unwrap()[..8], [1, 0, 0, 4, 1, 1, 0, 0])
+    }
+
+    #[test]
+    fn misplaced_section_content() {
+        assert!(assemble_source(".data\nHLT").is_err());
+        assert!(assemble_source(".code\n.word #1").is_err())
+    }
+
+    #[test]
+    fn executable_from_program() {
+        let content: &str =
+            ".data\nmsg: .asciiz \"hi\"\n.code\nHLT\n\nmain: LA $0 @msg\nLOADDB $1 $0";
+        let mut lexer: Lexer = Lexer::new(content, content.len());
+        lexer.tokenize();
+        let mut parser: Parser = Parser::new(lexer.tokens);
+        let program: Program = Program {
+            instructions: parser.parse().unwrap(),
+            ..Program::default()
+        };
+        let executable: Executable = program.to_executable().unwrap();
+        assert_eq!(executable.entry_point, 4);
+        assert_eq!(executable.ro_data, b"hi\0");
+        assert_eq!(executable.line_table, vec![(0, 4), (4, 6), (8, 7)]);
+        let mut vm: VM = VM::new();
+        vm.load_executable(&executable.to_bytes()).unwrap();
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(vm.registers[1], 'h' as i32)
+    }
+
+    #[test]
+    fn subroutine() {
+        // square $0 twice through a subroutine that preserves $16
+        let content: &str = "LOAD $0 #3\nLOAD $16 #9\nLA $31 @square\nCALL $31\nCALL $31\nHLT\n\
+            square: PUSH $16\nMUL $0 $0 $16\nADD $16 $4 $0\nPOP $16\nRET";
+        let mut vm: VM = VM::new();
+        vm.bytecode = assemble(content);
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(vm.registers[0], 81);
+        assert_eq!(vm.registers[16], 9)
+    }
+
+    #[test]
+    fn negative_immediates() {
+        let content: &str = "LOAD $1 #-5\nLOAD $2 #-32768\nLOAD $3 #32767\nLOAD $4 #0x7FFF\nHLT";
+        let mut vm: VM = VM::new();
+        vm.bytecode = assemble(content);
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(vm.registers[1..5], [-5, -32768, 32767, 32767])
+    }
+
+    #[test]
+    fn heap_array() {
+        // store 1 2 3 as words in the heap, then sum them back
+        let content: &str = "LOAD $0 #12\nALOC $0\nLOAD $1 #1\nLOAD $2 #2\nLOAD $3 #3\nLOAD $9 #0\n\
+            STOREW [ $9 ] $1\nSTOREW [ $9 + #4 ] $2\nSTOREW [ $9 + #8 ] $3\n\
+            LOADW $4 [ $9 ]\nLOADW $5 [ $9 + #4 ]\nLOADW $6 [ $9 + #8 ]\nADD $4 $5 $7\nADD $7 $6 $7\nHLT";
+        let mut vm: VM = VM::new();
+        vm.bytecode = assemble(content);
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(vm.registers[7], 6)
+    }
+
+    #[test]
+    fn macro_program() {
+        // keep the larger of two registers, each expansion has its own `done` label
+        let content: &str = ".macro max dst, a, b\n\
+            LA $31 @done\nADD \\a $0 \\dst\nGEQ \\a \\b\nJEQ $31\nADD \\b $0 \\dst\ndone:\n.endm\n\
+            LOAD $1 #3\nLOAD $2 #9\nmax $10, $1, $2\nmax $11, $2, $1\nHLT";
+        let program: Program = crate::assembler::assemble(content).unwrap();
+        let mut vm: VM = VM::new();
+        vm.bytecode = program.as_bytes().unwrap();
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!((vm.registers[10], vm.registers[11]), (9, 9))
+    }
+
+    #[test]
+    fn float_program() {
+        // mean of a .double table, then float literals pooled after it in the data section
+        let content: &str = ".data\ntable: .double #1.5 #2.5 #-1 #6\n.code\n\
+            main: LOADF %f0 #0.0\nLOAD $1 #0\nLOAD $2 #4\nLA $3 @table\n\
+            LOADF %f1 @table\nADDF %f0 %f1 %f0\nLOADDF %f1 #8\nADDF %f0 %f1 %f0\n\
+            LOADDF %f1 #16\nADDF %f0 %f1 %f0\nLOADDF %f1 #24\nADDF %f0 %f1 %f0\n\
+            ITOF %f2 $2\nDIVF %f0 %f2 %f3\nLOADF %f4 #2.25\nEQF %f3 %f4\n\
+            LOADF %f5 #-7.9\nFTOI $4 %f5\nHLT";
+        let executable: Executable = crate::assembler::assemble(content)
+            .unwrap()
+            .to_executable()
+            .unwrap();
+        assert_eq!(executable.ro_data.len(), 56);
+        assert_eq!(executable.ro_data[40..48], 2.25f64.to_be_bytes());
+        let mut vm: VM = VM::new();
+        vm.load(&executable);
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(vm.float_registers[3], 2.25);
+        assert!(vm.flags.zero);
+        assert_eq!(vm.registers[4], -7)
+    }
+
+    #[test]
+    fn float_operand_errors() {
+        let errors = |content: &str| -> Vec<String> {
+            crate::assembler::assemble(content)
+                .unwrap_err()
+                .errors
+                .into_iter()
+                .map(|err| err.message)
+                .collect()
+        };
+        assert_eq!(
+            errors("LOADF $0 #1.5\nADDF %f0 $1 %f2\nLOAD $0 #1.5\n.word #2.5"),
+            [
+                "LOADF expects 2 operand(s), operand 1 must be a float register",
+                "ADDF expects 3 operand(s), operand 2 must be a float register",
+                "LOAD expects 2 operand(s), operand 2 must be an integer",
+                ".word expects one or more integers",
+            ]
+        )
+    }
+
+    #[test]
+    fn compare_and_branch() {
+        // sum of 1 to 10 with a backward branch, then clamp it to 50 without a branch
+        let content: &str = "LOAD $0 #0\nLOAD $1 #1\nLOAD $2 #10\nLOAD $3 #50\n\
+            loop: ADD $0 $1 $0\nINC $1\nCMP $1 $2\nBLE @loop\n\
+            CMP $0 $3\nCMOVGT $0 $3\nBGE @end\nLOAD $0 #0\nend: HLT";
+        let program: Program = crate::assembler::assemble(content).unwrap();
+        let code: Vec<u8> = program.as_bytes().unwrap();
+        assert_eq!(code[28..32], [74, 255, 240, 0]);
+        assert_eq!(code[40..44], [75, 0, 4, 0]);
+        let mut vm: VM = VM::new();
+        vm.bytecode = code;
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(vm.registers[0], 50)
+    }
+
+    #[test]
+    fn branch_errors() {
+        let errors = |content: &str| -> Vec<String> {
+            crate::assembler::assemble(content)
+                .unwrap_err()
+                .errors
+                .into_iter()
+                .map(|err| err.message)
+                .collect()
+        };
+        assert_eq!(
+            errors("BGE $1"),
+            ["BGE expects 1 operand(s), operand 1 must be an offset or a label"]
+        );
+        assert_eq!(
+            errors(".extern far\nBZ @far\nBLT #40000\nBGT @nowhere\nBNZ @table\n.data\ntable: .byte #1"),
+            [
+                "branches only reach labels of their own file, 'far' is .extern",
+                "branch offset 40000 does not fit in 16 bits",
+                "undefined label 'nowhere'",
+                "branches only reach code labels, 'table' is in the .data section",
+            ]
+        )
+    }
 }
@@ -0,0 +1,79 @@
+/// segment of the assembled program a symbol points into
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Section {
+    Code,
+    Data,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    /// byte offset of the symbol in its section
+    pub offset: usize,
+    pub section: Section,
+}
+
+impl Symbol {
+    pub fn new(name: &str, offset: usize, section: Section) -> Self {
+        Self {
+            name: name.into(),
+            offset,
+            section,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self {
+            symbols: Vec::new(),
+        }
+    }
+
+    pub fn add_symbol(&mut self, symbol: Symbol) {
+        self.symbols.push(symbol);
+    }
+
+    pub fn has_symbol(&self, name: &str) -> bool {
+        self.symbols.iter().any(|symbol| symbol.name == name)
+    }
+
+    pub fn symbol_offset(&self, name: &str) -> Option<usize> {
+        self.symbols
+            .iter()
+            .find(|symbol| symbol.name == name)
+            .map(|symbol| symbol.offset)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn symbol_lookup() {
+        let mut symbols: SymbolTable = SymbolTable::new();
+        symbols.add_symbol(Symbol::new("start", 0, Section::Code));
+        symbols.add_symbol(Symbol::new("loop", 8, Section::Data));
+        assert!(symbols.has_symbol("loop"));
+        assert_eq!(symbols.symbol_offset("loop"), Some(8));
+        assert_eq!(symbols.symbol_offset("end"), None)
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{
+    assembler::symbols::{Section, SymbolTable},
+    executable::Executable,
+    instruction::{Opcode, OperandKind},
+    vm::INSTRUCTION_WIDTH,
+};
+
+/// number of bytes per `.byte` line in the data section
+const DATA_BYTES_PER_LINE: usize = 8;
+
+/// decode one instruction into assembly text, e.g. `LOAD $1 #500`
+/// returns None if the bytes are not a canonical encoding (unknown opcode,
+/// non-zero padding or a truncated instruction) since those would not re-assemble
+pub fn disassemble_instruction(bytes: &[u8]) -> Option<String> {
+    if bytes.len() != INSTRUCTION_WIDTH {
+        return None;
+    }
+    let opcode: Opcode = Opcode::from(bytes[0]);
+    if opcode == Opcode::NOP {
+        return None;
+    }
+
+    let mut text: String = format!("{:?}", opcode);
+    let mut position: usize = 1;
+    for kind in opcode.operand_kinds() {
+        match kind {
+            OperandKind::Register => {
+                text.push_str(&format!(" ${}", bytes[position]));
+                position += 1;
+            }
+            OperandKind::Integer => {
+                let field: [u8; 2] = [bytes[position], bytes[position + 1]];
+                // LOAD sign-extends its immediate, the other opcodes read it as unsigned
+                let value: i32 = match opcode {
+                    Opcode::LOAD => i16::from_be_bytes(field) as i32,
+                    _ => u16::from_be_bytes(field) as i32,
+                };
+                text.push_str(&format!(" #{}", value));
+                position += 2;
+            }
+            OperandKind::Memory => {
+                let (base, offset): (u8, u8) = (bytes[position], bytes[position + 1]);
+                text.push_str(&format!(" [ ${} + #{} ]", base, offset));
+                position += 2;
+            }
+            OperandKind::FloatRegister => {
+                text.push_str(&format!(" %f{}", bytes[position]));
+                position += 1;
+            }
+            OperandKind::Offset => {
+                let offset: i16 = i16::from_be_bytes([bytes[position], bytes[position + 1]]);
+                text.push_str(&format!(" #{}", offset));
+                position += 2;
+            }
+            OperandKind::Label | OperandKind::Float => return None,
+        }
+    }
+    if bytes[position..].iter().any(|byte| *byte != 0) {
+        return None;
+    }
+    Some(text)
+}
+
+fn symbol_lines(listing: &mut String, symbols: &SymbolTable, section: Section, offset: usize) {
+    for symbol in symbols.iter() {
+        if symbol.section != section || symbol.offset != offset {
+            continue;
+        }
+        // names generated by the assembler (macro-local labels, float literals)
+        // contain a dot so they can't clash with source labels, they are kept as comments
+        if symbol.name.contains('.') {
+            listing.push_str(&format!("; {}\n", symbol.name));
+        } else {
+            listing.push_str(&format!("{}:\n", symbol.name));
+        }
+    }
+}
+
+/// listing of a whole program, one `LOAD $1 #500 ; 0x0004` line per instruction
+/// preceded by the data section if there is one, addresses are comments so
+/// they can't clash with the labels of the program
+pub fn disassemble(executable: &Executable) -> String {
+    let mut listing: String = String::new();
+
+    if !executable.ro_data.is_empty() {
+        listing.push_str(".data\n");
+        let mut line: Vec<String> = Vec::new();
+        for (offset, byte) in executable.ro_data.iter().enumerate() {
+            let starts_symbol: bool = executable
+                .symbols
+                .iter()
+                .any(|symbol| symbol.section == Section::Data && symbol.offset == offset);
+            if !line.is_empty() && (starts_symbol || line.len() == DATA_BYTES_PER_LINE) {
+                listing.push_str(&format!(".byte {}\n", line.join(" ")));
+                line.clear();
+            }
+            symbol_lines(&mut listing, &executable.symbols, Section::Data, offset);
+            line.push(format!("#{}", byte));
+        }
+        listing.push_str(&format!(".byte {}\n", line.join(" ")));
+        listing.push_str(".code\n");
+    }
+
+    for (index, bytes) in executable.code.chunks(INSTRUCTION_WIDTH).enumerate() {
+        let offset: usize = index * INSTRUCTION_WIDTH;
+        symbol_lines(&mut listing, &executable.symbols, Section::Code, offset);
+        let text: String = match disassemble_instruction(bytes) {
+            Some(text) => text,
+            None => {
+                let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+                format!("??? {}", bytes.join(" "))
+            }
+        };
+        listing.push_str(&format!("{} ; {:#06X}\n", text, offset));
+    }
+    symbol_lines(
+        &mut listing,
+        &executable.symbols,
+        Section::Code,
+        executable.code.len(),
+    );
+    listing
+}
+
+#[cfg(test)]
+mod test {
+    use crate::assembler::assemble;
+
+    use super::*;
+
+    #[test]
+    fn single_instruction() {
+        assert_eq!(
+            disassemble_instruction(&[1, 1, 1, 244]),
+            Some("LOAD $1 #500".into())
+        );
+        assert_eq!(
+            disassemble_instruction(&[2, 0, 1, 2]),
+            Some("ADD $0 $1 $2".into())
+        );
+        assert_eq!(disassemble_instruction(&[0, 0, 0, 0]), Some("HLT".into()));
+        assert_eq!(
+            disassemble_instruction(&[1, 1, 255, 251]),
+            Some("LOAD $1 #-5".into())
+        );
+        assert_eq!(
+            disassemble_instruction(&[41, 3, 8, 1]),
+            Some("STOREW [ $3 + #8 ] $1".into())
+        );
+        assert_eq!(
+            disassemble_instruction(&[64, 3, 31, 0]),
+            Some("FTOI $3 %f31".into())
+        );
+        assert_eq!(
+            disassemble_instruction(&[72, 255, 248, 0]),
+            Some("BLT #-8".into())
+        );
+        assert_eq!(disassemble_instruction(&[200, 0, 0, 0]), None);
+        assert_eq!(disassemble_instruction(&[17, 1, 0, 1]), None);
+        assert_eq!(disassemble_instruction(&[1, 1, 1]), None)
+    }
+
+    #[test]
+    fn listing() {
+        let executable: Executable = Executable {
+            code: vec![1, 1, 1, 244, 0, 0, 0, 0],
+            ..Default::default()
+        };
+        assert_eq!(
+            disassemble(&executable),
+            "LOAD $1 #500 ; 0x0000\nHLT ; 0x0004\n"
+        )
+    }
+
+    #[test]
+    fn listing_re_assembles() {
+        let content: &str = ".data\nmsg: .asciiz \"hello world\"\nn: .word #-2\n.code\n\
+            LA $0 @msg\nLOAD $9 #3\nLOAD $8 #-5\nmain: LOADDB $1 $0\nINC $0\nDEC $9\nRSHTI $1 #2\n\
+            ADD $1 $1 $2\nNOT $2 $3\nJMP $9\nLOADF %f1 #0.5\nL0004: LA $5 @L0004\nend: HLT";
+        let executable: Executable = assemble(content).unwrap().to_executable().unwrap();
+        let listing: String = disassemble(&executable);
+        let reassembled: Executable = assemble(&listing).unwrap().to_executable().unwrap();
+        assert_eq!(reassembled.code, executable.code);
+        assert_eq!(reassembled.ro_data, executable.ro_data);
+        assert_eq!(reassembled.entry_point, executable.entry_point)
+    }
+}
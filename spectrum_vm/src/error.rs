@@ -0,0 +1,74 @@
+use std::{fmt, io};
+
+use crate::{
+    assembler::{diagnostics::Diagnostics, linker::LinkError, parser::ParseError},
+    executable::LoadError,
+};
+
+/// errors of the file helpers of the library, wrapping those of each stage
+/// every variant but `Link` names the file it comes from
+#[derive(Debug)]
+pub enum Error {
+    /// the file could not be read
+    Io {
+        path: String,
+        error: io::Error,
+    },
+    /// the source has errors, `source` is its text the diagnostics point into
+    Assemble {
+        path: String,
+        source: String,
+        diagnostics: Diagnostics,
+    },
+    /// the source assembled but couldn't be turned into an executable or an object
+    Parse {
+        path: String,
+        error: Box<ParseError>,
+    },
+    Link(LinkError),
+    /// the file is not a valid Spectrum executable
+    Load {
+        path: String,
+        error: LoadError,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, error } => write!(f, "couldn't read {} : {}", path, error),
+            Error::Assemble {
+                path,
+                source,
+                diagnostics,
+            } => write!(
+                f,
+                "{} error(s) in {}\n{}",
+                diagnostics.errors.len(),
+                path,
+                diagnostics.render(source).trim_end()
+            ),
+            Error::Parse { path, error } => write!(f, "{} : {}", path, error),
+            Error::Link(error) => write!(f, "{}", error),
+            Error::Load { path, error } => write!(f, "{} : {}", path, error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { error, .. } => Some(error),
+            Error::Assemble { diagnostics, .. } => Some(diagnostics),
+            Error::Parse { error, .. } => Some(error.as_ref()),
+            Error::Link(error) => Some(error),
+            Error::Load { error, .. } => Some(error),
+        }
+    }
+}
+
+impl From<LinkError> for Error {
+    fn from(error: LinkError) -> Self {
+        Error::Link(error)
+    }
+}
@@ -0,0 +1,357 @@
+use std::fmt;
+
+use crate::{
+    assembler::symbols::{Section, Symbol, SymbolTable},
+    utils::crc32,
+};
+
+/// Spectrum executable layout, every integer is big endian
+///
+/// header (HEADER_LEN bytes)
+///   magic          4 bytes  "SPVM"
+///   version        u16
+///   section count  u16
+///   entry point    u32      offset in the code section
+///   checksum       u32      crc32 of the whole file with this field zeroed
+/// section table (SECTION_ENTRY_LEN bytes per section)
+///   kind           u8       see SectionKind
+///   offset         u32      from the start of the file
+///   length         u32
+/// section payloads
+pub const MAGIC: [u8; 4] = *b"SPVM";
+pub const FORMAT_VERSION: u16 = 1;
+pub const HEADER_LEN: usize = 16;
+pub const SECTION_ENTRY_LEN: usize = 9;
+const CHECKSUM_OFFSET: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SectionKind {
+    Code = 1,
+    RoData = 2,
+    /// count u32, then per symbol: section u8, offset u32, name length u16, name
+    Symbols = 3,
+    /// count u32, then per instruction: code offset u32, source line u32
+    Debug = 4,
+}
+
+impl TryFrom<u8> for SectionKind {
+    type Error = LoadError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(SectionKind::Code),
+            2 => Ok(SectionKind::RoData),
+            3 => Ok(SectionKind::Symbols),
+            4 => Ok(SectionKind::Debug),
+            _ => Err(LoadError::UnknownSection { kind: value }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadError {
+    BadMagic { found: Vec<u8> },
+    UnsupportedVersion { found: u16 },
+    Truncated,
+    ChecksumMismatch { expected: u32, found: u32 },
+    UnknownSection { kind: u8 },
+    DuplicateSection { kind: SectionKind },
+    MissingCodeSection,
+    EntryPointOutOfBounds { entry_point: usize },
+    MalformedSection { kind: SectionKind },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::BadMagic { found } => {
+                write!(f, "not a Spectrum executable (magic bytes {:02X?})", found)
+            }
+            LoadError::UnsupportedVersion { found } => write!(
+                f,
+                "unsupported executable format version {} (expected {})",
+                found, FORMAT_VERSION
+            ),
+            LoadError::Truncated => write!(f, "executable is truncated"),
+            LoadError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "checksum mismatch (header says {:#010X}, content is {:#010X})",
+                expected, found
+            ),
+            LoadError::UnknownSection { kind } => write!(f, "unknown section kind {}", kind),
+            LoadError::DuplicateSection { kind } => write!(f, "duplicate {:?} section", kind),
+            LoadError::MissingCodeSection => write!(f, "executable has no code section"),
+            LoadError::EntryPointOutOfBounds { entry_point } => {
+                write!(f, "entry point {:#06X} is outside the code", entry_point)
+            }
+            LoadError::MalformedSection { kind } => write!(f, "malformed {:?} section", kind),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Executable {
+    pub entry_point: usize,
+    pub code: Vec<u8>,
+    pub ro_data: Vec<u8>,
+    pub symbols: SymbolTable,
+    /// (code offset, source line) of every instruction
+    pub line_table: Vec<(usize, usize)>,
+}
+
+impl Executable {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut symbols: Vec<u8> = Vec::new();
+        symbols.extend_from_slice(&(self.symbols.len() as u32).to_be_bytes());
+        for symbol in self.symbols.iter() {
+            symbols.push(match symbol.section {
+                Section::Code => 0,
+                Section::Data => 1,
+            });
+            symbols.extend_from_slice(&(symbol.offset as u32).to_be_bytes());
+            symbols.extend_from_slice(&(symbol.name.len() as u16).to_be_bytes());
+            symbols.extend_from_slice(symbol.name.as_bytes());
+        }
+
+        let mut debug: Vec<u8> = Vec::new();
+        debug.extend_from_slice(&(self.line_table.len() as u32).to_be_bytes());
+        for (offset, line) in &self.line_table {
+            debug.extend_from_slice(&(*offset as u32).to_be_bytes());
+            debug.extend_from_slice(&(*line as u32).to_be_bytes());
+        }
+
+        let sections: [(SectionKind, &[u8]); 4] = [
+            (SectionKind::Code, &self.code),
+            (SectionKind::RoData, &self.ro_data),
+            (SectionKind::Symbols, &symbols),
+            (SectionKind::Debug, &debug),
+        ];
+
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+        bytes.extend_from_slice(&(sections.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&(self.entry_point as u32).to_be_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+
+        let mut payload_offset: usize = HEADER_LEN + sections.len() * SECTION_ENTRY_LEN;
+        for (kind, payload) in &sections {
+            bytes.push(*kind as u8);
+            bytes.extend_from_slice(&(payload_offset as u32).to_be_bytes());
+            bytes.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+            payload_offset += payload.len();
+        }
+        for (_, payload) in &sections {
+            bytes.extend_from_slice(payload);
+        }
+
+        let checksum: u32 = crc32(&bytes);
+        bytes[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].copy_from_slice(&checksum.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadError> {
+        if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+            return Err(LoadError::BadMagic {
+                found: bytes.iter().take(MAGIC.len()).copied().collect(),
+            });
+        }
+        let mut reader: Reader = Reader::new(bytes, MAGIC.len());
+        let version: u16 = reader.read_u16()?;
+        if version != FORMAT_VERSION {
+            return Err(LoadError::UnsupportedVersion { found: version });
+        }
+        let section_count: usize = reader.read_u16()? as usize;
+        let entry_point: usize = reader.read_u32()? as usize;
+        let expected_checksum: u32 = reader.read_u32()?;
+
+        let mut zeroed: Vec<u8> = bytes.to_vec();
+        zeroed[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].copy_from_slice(&[0; 4]);
+        let checksum: u32 = crc32(&zeroed);
+        if checksum != expected_checksum {
+            return Err(LoadError::ChecksumMismatch {
+                expected: expected_checksum,
+                found: checksum,
+            });
+        }
+
+        let mut executable: Executable = Executable {
+            entry_point,
+            ..Default::default()
+        };
+        let mut seen: Vec<SectionKind> = Vec::new();
+        for _ in 0..section_count {
+            let kind: SectionKind = SectionKind::try_from(reader.read_u8()?)?;
+            let offset: usize = reader.read_u32()? as usize;
+            let len: usize = reader.read_u32()? as usize;
+            if seen.contains(&kind) {
+                return Err(LoadError::DuplicateSection { kind });
+            }
+            seen.push(kind);
+            let payload: &[u8] = offset
+                .checked_add(len)
+                .and_then(|end| bytes.get(offset..end))
+                .ok_or(LoadError::Truncated)?;
+            match kind {
+                SectionKind::Code => executable.code = payload.to_vec(),
+                SectionKind::RoData => executable.ro_data = payload.to_vec(),
+                SectionKind::Symbols => {
+                    executable.symbols =
+                        read_symbols(payload).map_err(|_| LoadError::MalformedSection { kind })?
+                }
+                SectionKind::Debug => {
+                    executable.line_table = read_line_table(payload)
+                        .map_err(|_| LoadError::MalformedSection { kind })?
+                }
+            }
+        }
+
+        if !seen.contains(&SectionKind::Code) {
+            return Err(LoadError::MissingCodeSection);
+        }
+        if entry_point > executable.code.len() {
+            return Err(LoadError::EntryPointOutOfBounds { entry_point });
+        }
+        Ok(executable)
+    }
+}
+
+fn read_symbols(payload: &[u8]) -> Result<SymbolTable, LoadError> {
+    let mut reader: Reader = Reader::new(payload, 0);
+    let mut symbols: SymbolTable = SymbolTable::new();
+    for _ in 0..reader.read_u32()? {
+        let section: Section = match reader.read_u8()? {
+            0 => Section::Code,
+            1 => Section::Data,
+            _ => return Err(LoadError::Truncated),
+        };
+        let offset: usize = reader.read_u32()? as usize;
+        let name_len: usize = reader.read_u16()? as usize;
+        let name: &str =
+            std::str::from_utf8(reader.read_bytes(name_len)?).map_err(|_| LoadError::Truncated)?;
+        symbols.add_symbol(Symbol::new(name, offset, section));
+    }
+    reader.expect_end()?;
+    Ok(symbols)
+}
+
+fn read_line_table(payload: &[u8]) -> Result<Vec<(usize, usize)>, LoadError> {
+    let mut reader: Reader = Reader::new(payload, 0);
+    let mut line_table: Vec<(usize, usize)> = Vec::new();
+    for _ in 0..reader.read_u32()? {
+        let offset: usize = reader.read_u32()? as usize;
+        let line: usize = reader.read_u32()? as usize;
+        line_table.push((offset, line));
+    }
+    reader.expect_end()?;
+    Ok(line_table)
+}
+
+/// bounds checked big endian cursor over a byte slice
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], position: usize) -> Self {
+        Self { bytes, position }
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], LoadError> {
+        let bytes: &'a [u8] = self.bytes;
+        let result: &'a [u8] = bytes
+            .get(self.position..self.position + len)
+            .ok_or(LoadError::Truncated)?;
+        self.position += len;
+        Ok(result)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, LoadError> {
+        let bytes: &[u8] = self.read_bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, LoadError> {
+        let bytes: &[u8] = self.read_bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn expect_end(&self) -> Result<(), LoadError> {
+        if self.position == self.bytes.len() {
+            Ok(())
+        } else {
+            Err(LoadError::Truncated)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn executable() -> Executable {
+        let mut symbols: SymbolTable = SymbolTable::new();
+        symbols.add_symbol(Symbol::new("main", 4, Section::Code));
+        symbols.add_symbol(Symbol::new("msg", 0, Section::Data));
+        Executable {
+            entry_point: 4,
+            code: vec![0, 0, 0, 0, 1, 1, 1, 244],
+            ro_data: b"hi\0".to_vec(),
+            symbols,
+            line_table: vec![(0, 1), (4, 3)],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes: Vec<u8> = executable().to_bytes();
+        assert_eq!(bytes[..4], MAGIC);
+        assert_eq!(Executable::from_bytes(&bytes), Ok(executable()))
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut bytes: Vec<u8> = executable().to_bytes();
+        bytes[0] = b'X';
+        assert!(matches!(
+            Executable::from_bytes(&bytes),
+            Err(LoadError::BadMagic { .. })
+        ));
+        assert!(matches!(
+            Executable::from_bytes(&[1, 1, 1, 244]),
+            Err(LoadError::BadMagic { .. })
+        ))
+    }
+
+    #[test]
+    fn bad_version() {
+        let mut bytes: Vec<u8> = executable().to_bytes();
+        bytes[5] = 9;
+        assert_eq!(
+            Executable::from_bytes(&bytes),
+            Err(LoadError::UnsupportedVersion { found: 9 })
+        )
+    }
+
+    #[test]
+    fn corrupted_content() {
+        let mut bytes: Vec<u8> = executable().to_bytes();
+        let last: usize = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        assert!(matches!(
+            Executable::from_bytes(&bytes),
+            Err(LoadError::ChecksumMismatch { .. })
+        ));
+        assert!(matches!(
+            Executable::from_bytes(&bytes[..10]),
+            Err(LoadError::Truncated)
+        ))
+    }
+}
@@ -24,6 +24,86 @@ pub enum Opcode {
     LFST,
     RROR,
     LROR,
+    AND,
+    OR,
+    XOR,
+    NOT,
+    RSHTI,
+    LFSTI,
+    RRORI,
+    LRORI,
+    LOADDB,
+    LOADDW,
+    // stack instructions move 32-bit words through `VM::stack`
+    //
+    // calling convention:
+    // - arguments are passed in $0 to $3 and the result is returned in $0
+    // - $4 to $15 may be overwritten by the callee, the caller saves them if needed
+    // - $16 to $29 belong to the caller, a callee that uses them PUSHes and POPs them
+    // - $30 and $31 hold addresses for CALL and jumps (`LA $31 @function` then `CALL $31`)
+    PUSH,
+    POP,
+    CALL,
+    RET,
+    // heap instructions address `VM::heap` with a base register plus an unsigned 8-bit offset
+    LOADB,
+    LOADW,
+    STOREB,
+    STOREW,
+    /// `SYSCALL #n` runs the host function registered as system call n, see `vm::syscall`
+    SYSCALL,
+    // ADD, SUB and MUL wrap around, ADDO, SUBO and MULO fault on signed overflow
+    ADDO,
+    SUBO,
+    MULO,
+    // jumps on the flags set by arithmetic: zero, carry and overflow
+    JZ,
+    JNZ,
+    JC,
+    JNC,
+    JO,
+    JNO,
+    // float instructions work on `VM::float_registers`, written `%f0` to `%f31`
+    // LOADDF loads the f64 at a read-only data address, the LOADF pseudo-instruction
+    // places its float literal in the data section and loads it with LOADDF
+    LOADDF,
+    ADDF,
+    SUBF,
+    MULF,
+    DIVF,
+    // float relation tests set the zero flag like EQ ... LEQ, they are false on NaN except NEQF
+    EQF,
+    NEQF,
+    GTF,
+    GEQF,
+    LEF,
+    LEQF,
+    // `ITOF %f $r` converts an integer register, `FTOI $r %f` truncates toward zero
+    // and saturates at the bounds of i32, NaN converts to 0
+    ITOF,
+    FTOI,
+    // `CMP $a $b` sets the flags of a - b, the jumps, branches and moves below
+    // test them as signed relations
+    CMP,
+    JLT,
+    JGT,
+    JLE,
+    JGE,
+    // relative branches: `BLT @label` or `BLT #offset`, the offset is a signed 16-bit
+    // number of bytes counted from the next instruction
+    BZ,
+    BNZ,
+    BLT,
+    BGT,
+    BLE,
+    BGE,
+    // `CMOVLT $dst $src` copies $src into $dst if the condition holds
+    CMOVZ,
+    CMOVNZ,
+    CMOVLT,
+    CMOVGT,
+    CMOVLE,
+    CMOVGE,
     NOP,
 }
 
@@ -50,6 +130,68 @@ impl From<u8> for Opcode {
             17 => Opcode::INC,
             18 => Opcode::DEC,
             19 => Opcode::ALOC,
+            20 => Opcode::RSHT,
+            21 => Opcode::LFST,
+            22 => Opcode::RROR,
+            23 => Opcode::LROR,
+            24 => Opcode::AND,
+            25 => Opcode::OR,
+            26 => Opcode::XOR,
+            27 => Opcode::NOT,
+            28 => Opcode::RSHTI,
+            29 => Opcode::LFSTI,
+            30 => Opcode::RRORI,
+            31 => Opcode::LRORI,
+            32 => Opcode::LOADDB,
+            33 => Opcode::LOADDW,
+            34 => Opcode::PUSH,
+            35 => Opcode::POP,
+            36 => Opcode::CALL,
+            37 => Opcode::RET,
+            38 => Opcode::LOADB,
+            39 => Opcode::LOADW,
+            40 => Opcode::STOREB,
+            41 => Opcode::STOREW,
+            42 => Opcode::SYSCALL,
+            43 => Opcode::ADDO,
+            44 => Opcode::SUBO,
+            45 => Opcode::MULO,
+            46 => Opcode::JZ,
+            47 => Opcode::JNZ,
+            48 => Opcode::JC,
+            49 => Opcode::JNC,
+            50 => Opcode::JO,
+            51 => Opcode::JNO,
+            52 => Opcode::LOADDF,
+            53 => Opcode::ADDF,
+            54 => Opcode::SUBF,
+            55 => Opcode::MULF,
+            56 => Opcode::DIVF,
+            57 => Opcode::EQF,
+            58 => Opcode::NEQF,
+            59 => Opcode::GTF,
+            60 => Opcode::GEQF,
+            61 => Opcode::LEF,
+            62 => Opcode::LEQF,
+            63 => Opcode::ITOF,
+            64 => Opcode::FTOI,
+            65 => Opcode::CMP,
+            66 => Opcode::JLT,
+            67 => Opcode::JGT,
+            68 => Opcode::JLE,
+            69 => Opcode::JGE,
+            70 => Opcode::BZ,
+            71 => Opcode::BNZ,
+            72 => Opcode::BLT,
+            73 => Opcode::BGT,
+            74 => Opcode::BLE,
+            75 => Opcode::BGE,
+            76 => Opcode::CMOVZ,
+            77 => Opcode::CMOVNZ,
+            78 => Opcode::CMOVLT,
+            79 => Opcode::CMOVGT,
+            80 => Opcode::CMOVLE,
+            81 => Opcode::CMOVGE,
             _ => Opcode::NOP,
         }
     }
@@ -78,7 +220,169 @@ impl From<&str> for Opcode {
             "INC" => Opcode::INC,
             "DEC" => Opcode::DEC,
             "ALOC" => Opcode::ALOC,
+            "RSHT" => Opcode::RSHT,
+            "LFST" => Opcode::LFST,
+            "RROR" => Opcode::RROR,
+            "LROR" => Opcode::LROR,
+            "AND" => Opcode::AND,
+            "OR" => Opcode::OR,
+            "XOR" => Opcode::XOR,
+            "NOT" => Opcode::NOT,
+            "RSHTI" => Opcode::RSHTI,
+            "LFSTI" => Opcode::LFSTI,
+            "RRORI" => Opcode::RRORI,
+            "LRORI" => Opcode::LRORI,
+            "LOADDB" => Opcode::LOADDB,
+            "LOADDW" => Opcode::LOADDW,
+            "PUSH" => Opcode::PUSH,
+            "POP" => Opcode::POP,
+            "CALL" => Opcode::CALL,
+            "RET" => Opcode::RET,
+            "LOADB" => Opcode::LOADB,
+            "LOADW" => Opcode::LOADW,
+            "STOREB" => Opcode::STOREB,
+            "STOREW" => Opcode::STOREW,
+            "SYSCALL" => Opcode::SYSCALL,
+            "ADDO" => Opcode::ADDO,
+            "SUBO" => Opcode::SUBO,
+            "MULO" => Opcode::MULO,
+            "JZ" => Opcode::JZ,
+            "JNZ" => Opcode::JNZ,
+            "JC" => Opcode::JC,
+            "JNC" => Opcode::JNC,
+            "JO" => Opcode::JO,
+            "JNO" => Opcode::JNO,
+            "LOADDF" => Opcode::LOADDF,
+            "ADDF" => Opcode::ADDF,
+            "SUBF" => Opcode::SUBF,
+            "MULF" => Opcode::MULF,
+            "DIVF" => Opcode::DIVF,
+            "EQF" => Opcode::EQF,
+            "NEQF" => Opcode::NEQF,
+            "GTF" => Opcode::GTF,
+            "GEQF" => Opcode::GEQF,
+            "LEF" => Opcode::LEF,
+            "LEQF" => Opcode::LEQF,
+            "ITOF" => Opcode::ITOF,
+            "FTOI" => Opcode::FTOI,
+            "CMP" => Opcode::CMP,
+            "JLT" => Opcode::JLT,
+            "JGT" => Opcode::JGT,
+            "JLE" => Opcode::JLE,
+            "JGE" => Opcode::JGE,
+            "BZ" => Opcode::BZ,
+            "BNZ" => Opcode::BNZ,
+            "BLT" => Opcode::BLT,
+            "BGT" => Opcode::BGT,
+            "BLE" => Opcode::BLE,
+            "BGE" => Opcode::BGE,
+            "CMOVZ" => Opcode::CMOVZ,
+            "CMOVNZ" => Opcode::CMOVNZ,
+            "CMOVLT" => Opcode::CMOVLT,
+            "CMOVGT" => Opcode::CMOVGT,
+            "CMOVLE" => Opcode::CMOVLE,
+            "CMOVGE" => Opcode::CMOVGE,
             _ => Opcode::NOP,
         }
     }
 }
+
+/// kind of operand an instruction expects in a given position
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperandKind {
+    Register,
+    /// an integer literal or a label reference resolved to its address
+    Integer,
+    /// a label reference only, used by pseudo-instructions
+    Label,
+    /// a heap address `[ $base + #offset ]`, encoded as the base register then the offset
+    Memory,
+    /// a float register `%f0`, encoded like a register
+    FloatRegister,
+    /// a float literal or a data label, used by the LOADF pseudo-instruction
+    Float,
+    /// a signed 16-bit byte offset from the next instruction, or a label it is computed from
+    Offset,
+}
+
+impl Opcode {
+    /// operand signature of the instruction, in assembly order
+    pub fn operand_kinds(&self) -> &'static [OperandKind] {
+        use OperandKind::{FloatRegister, Integer, Memory, Offset, Register};
+        match self {
+            Opcode::BZ | Opcode::BNZ | Opcode::BLT | Opcode::BGT | Opcode::BLE | Opcode::BGE => {
+                &[Offset]
+            }
+            Opcode::CMP
+            | Opcode::CMOVZ
+            | Opcode::CMOVNZ
+            | Opcode::CMOVLT
+            | Opcode::CMOVGT
+            | Opcode::CMOVLE
+            | Opcode::CMOVGE => &[Register, Register],
+            Opcode::LOADDF => &[FloatRegister, Integer],
+            Opcode::ADDF | Opcode::SUBF | Opcode::MULF | Opcode::DIVF => {
+                &[FloatRegister, FloatRegister, FloatRegister]
+            }
+            Opcode::EQF
+            | Opcode::NEQF
+            | Opcode::GTF
+            | Opcode::GEQF
+            | Opcode::LEF
+            | Opcode::LEQF => &[FloatRegister, FloatRegister],
+            Opcode::ITOF => &[FloatRegister, Register],
+            Opcode::FTOI => &[Register, FloatRegister],
+            Opcode::HLT | Opcode::RET | Opcode::NOP => &[],
+            Opcode::SYSCALL => &[Integer],
+            Opcode::LOADB | Opcode::LOADW => &[Register, Memory],
+            Opcode::STOREB | Opcode::STOREW => &[Memory, Register],
+            Opcode::LOAD | Opcode::RSHTI | Opcode::LFSTI | Opcode::RRORI | Opcode::LRORI => {
+                &[Register, Integer]
+            }
+            Opcode::ADD
+            | Opcode::SUB
+            | Opcode::MUL
+            | Opcode::ADDO
+            | Opcode::SUBO
+            | Opcode::MULO
+            | Opcode::DIV
+            | Opcode::RSHT
+            | Opcode::LFST
+            | Opcode::RROR
+            | Opcode::LROR
+            | Opcode::AND
+            | Opcode::OR
+            | Opcode::XOR => &[Register, Register, Register],
+            Opcode::EQ
+            | Opcode::NEQ
+            | Opcode::GT
+            | Opcode::GEQ
+            | Opcode::LE
+            | Opcode::LEQ
+            | Opcode::NOT
+            | Opcode::LOADDB
+            | Opcode::LOADDW => &[Register, Register],
+            Opcode::JEQ
+            | Opcode::JNEQ
+            | Opcode::JZ
+            | Opcode::JNZ
+            | Opcode::JC
+            | Opcode::JNC
+            | Opcode::JO
+            | Opcode::JNO
+            | Opcode::JLT
+            | Opcode::JGT
+            | Opcode::JLE
+            | Opcode::JGE
+            | Opcode::JMP
+            | Opcode::JMPF
+            | Opcode::JMPB
+            | Opcode::INC
+            | Opcode::DEC
+            | Opcode::ALOC
+            | Opcode::PUSH
+            | Opcode::POP
+            | Opcode::CALL => &[Register],
+        }
+    }
+}
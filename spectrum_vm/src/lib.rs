@@ -0,0 +1,162 @@
+//! Spectrum assembler and virtual machine
+//!
+//! `assemble` turns a source into a `Program`, `VM::load_program` loads it
+//! and `VM::run`, `VM::run_for`, `VM::run_until` or `VM::step` execute it
+//!
+//! the file helpers `assemble_file`, `link_files` and `read_executable` fail with
+//! an `Error` wrapping the error of the stage that failed
+
+use std::{fs, path::Path};
+
+pub mod assembler;
+pub mod disassembler;
+pub mod error;
+pub mod executable;
+pub mod instruction;
+pub mod repl;
+pub mod utils;
+pub mod vm;
+
+pub use crate::{
+    assembler::{
+        assemble, assemble_in,
+        diagnostics::Diagnostics,
+        linker::{link, LinkError, Object},
+        parser::ParseError,
+        program::Program,
+    },
+    error::Error,
+    executable::{Executable, LoadError},
+    vm::{
+        error::{ExitReason, VmError},
+        flags::Flags,
+        outcome::{RunOutcome, Step, StepOutcome},
+        syscall::{Console, SyscallContext},
+        VM,
+    },
+};
+
+/// assemble a source file, its `.include` paths are relative to its directory
+pub fn assemble_source(file_path: &str) -> Result<Program, Error> {
+    let source: String = fs::read_to_string(file_path).map_err(|error| Error::Io {
+        path: file_path.into(),
+        error,
+    })?;
+    let directory: &Path = Path::new(file_path).parent().unwrap_or(Path::new("."));
+    match assembler::assemble_in(&source, directory) {
+        Ok(program) => Ok(program),
+        Err(mut diagnostics) => {
+            diagnostics.file = Some(file_path.into());
+            Err(Error::Assemble {
+                path: file_path.into(),
+                source,
+                diagnostics,
+            })
+        }
+    }
+}
+
+pub fn assemble_file(file_path: &str) -> Result<Executable, Error> {
+    assemble_source(file_path)?
+        .to_executable()
+        .map_err(|error| Error::Parse {
+            path: file_path.into(),
+            error: Box::new(error),
+        })
+}
+
+/// assemble every file into an object and link them, in order
+pub fn link_files(file_paths: &[String]) -> Result<Executable, Error> {
+    let mut objects: Vec<Object> = Vec::new();
+    for file_path in file_paths {
+        let object: Object = assemble_source(file_path)?
+            .to_object(file_path)
+            .map_err(|error| Error::Parse {
+                path: file_path.clone(),
+                error: Box::new(error),
+            })?;
+        objects.push(object);
+    }
+    Ok(link(&objects)?)
+}
+
+/// `.asm` files are assembled, other files are Spectrum executables if they start
+/// with the `SPVM` magic and bare bytecode otherwise, `raw` loads them as bytecode
+/// even with the magic
+pub fn read_executable(file_path: &str, raw: bool) -> Result<Executable, Error> {
+    if file_path.ends_with(".asm") {
+        return assemble_file(file_path);
+    }
+    let bytes: Vec<u8> = fs::read(file_path).map_err(|error| Error::Io {
+        path: file_path.into(),
+        error,
+    })?;
+    if raw || !bytes.starts_with(&executable::MAGIC) {
+        return Ok(Executable {
+            code: bytes,
+            ..Default::default()
+        });
+    }
+    Executable::from_bytes(&bytes).map_err(|error| Error::Load {
+        path: file_path.into(),
+        error,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn embed_vm() {
+        let program: Program =
+            assemble("main: LOAD $0 #20\nLOAD $1 #22\nADD $0 $1 $2\nHLT").unwrap();
+        let mut vm: VM = VM::new();
+        vm.load_program(&program).unwrap();
+        assert_eq!(vm.step().outcome, StepOutcome::Running);
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(vm.registers[2], 42);
+        let diagnostics: Diagnostics = assemble("LOAD $0").unwrap_err();
+        assert_eq!(diagnostics.errors.len(), 1)
+    }
+
+    #[test]
+    fn typed_file_errors() {
+        let path = std::env::temp_dir().join("spectrum_lib_errors.asm");
+        let path: String = path.display().to_string();
+        assert!(matches!(
+            read_executable("missing.bin", false),
+            Err(Error::Io { .. })
+        ));
+        fs::write(&path, "LOAD $0").unwrap();
+        match read_executable(&path, false) {
+            Err(Error::Assemble { diagnostics, .. }) => assert_eq!(diagnostics.errors.len(), 1),
+            other => panic!("expected assembly errors, got {:?}", other),
+        }
+        fs::write(&path, ".extern f\nLA $0 @f").unwrap();
+        assert!(matches!(assemble_file(&path), Err(Error::Parse { .. })));
+        assert!(matches!(
+            link_files(std::slice::from_ref(&path)),
+            Err(Error::Link(LinkError::UndefinedSymbol { .. }))
+        ));
+        fs::write(&path, "INC $0").unwrap();
+        let bin_path: String = path.replace(".asm", ".bin");
+        fs::write(&bin_path, [1, 2, 3, 4]).unwrap();
+        assert_eq!(
+            read_executable(&bin_path, false).unwrap().code,
+            [1, 2, 3, 4]
+        );
+        fs::write(&bin_path, b"SPVM\x00").unwrap();
+        assert!(matches!(
+            read_executable(&bin_path, false),
+            Err(Error::Load {
+                error: LoadError::Truncated,
+                ..
+            })
+        ));
+        assert_eq!(read_executable(&bin_path, true).unwrap().code, b"SPVM\x00");
+        for path in [path, bin_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -1,22 +1,288 @@
-use std::env;
+use std::{env, fs, process::ExitCode};
 
-use crate::{repl::cli::REPL, vm::VM};
+use spectrum_vm::{
+    disassembler, link_files, read_executable, repl::cli::REPL, Executable, RunOutcome, VM,
+};
 
-mod assembler;
-mod repl;
+const USAGE: &str =
+    "usage: spectrum_vm [--dump-registers] [--trace] [--max-steps <n>] [--raw] <file.asm|file.bin>
+       spectrum_vm build <file.asm>... [-o <file.bin>]
+       spectrum_vm disasm [--raw] <file.asm|file.bin>
+files without the SPVM executable header run as bare bytecode, --raw forces it";
 
-pub mod instruction;
-pub mod utils;
-pub mod vm;
+/// exit status when the file could not be read or the arguments are invalid
+const EXIT_USAGE: u8 = 1;
+/// exit status when the vm faulted at runtime
+const EXIT_FAULT: u8 = 2;
+/// exit status when the program did not halt within `--max-steps`
+const EXIT_STEP_LIMIT: u8 = 3;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
+#[derive(Debug, Default, PartialEq)]
+struct RunOptions {
+    file_path: String,
+    dump_registers: bool,
+    trace: bool,
+    max_steps: Option<usize>,
+    raw: bool,
+}
+
+impl RunOptions {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut options: RunOptions = RunOptions::default();
+        let mut file_path: Option<String> = None;
+        let mut iterator = args.iter();
+        while let Some(arg) = iterator.next() {
+            match arg.as_str() {
+                "--dump-registers" => options.dump_registers = true,
+                "--trace" => options.trace = true,
+                "--raw" => options.raw = true,
+                "--max-steps" => {
+                    let value: &String = iterator.next().ok_or("--max-steps expects a value")?;
+                    let value: usize = value
+                        .parse()
+                        .map_err(|_| format!("invalid --max-steps value '{}'", value))?;
+                    options.max_steps = Some(value);
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{}'", flag)),
+                path => {
+                    if file_path.is_some() {
+                        return Err(format!("unexpected argument '{}'", path));
+                    }
+                    file_path = Some(path.into());
+                }
+            }
+        }
+        options.file_path = file_path.ok_or("missing input file")?;
+        Ok(options)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct BuildOptions {
+    /// sources linked into one executable, in order
+    input_paths: Vec<String>,
+    output_path: String,
+}
+
+impl BuildOptions {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut input_paths: Vec<String> = Vec::new();
+        let mut output_path: Option<String> = None;
+        let mut iterator = args.iter();
+        while let Some(arg) = iterator.next() {
+            match arg.as_str() {
+                "-o" => output_path = Some(iterator.next().ok_or("-o expects a value")?.clone()),
+                flag if flag.starts_with('-') => return Err(format!("unknown flag '{}'", flag)),
+                path => input_paths.push(path.into()),
+            }
+        }
+        let first_path: &String = input_paths.first().ok_or("missing input file")?;
+        let output_path: String = output_path.unwrap_or_else(|| {
+            let stem: &str = first_path.strip_suffix(".asm").unwrap_or(first_path);
+            format!("{}.bin", stem)
+        });
+        Ok(Self {
+            input_paths,
+            output_path,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct DisasmOptions {
+    file_path: String,
+    raw: bool,
+}
+
+impl DisasmOptions {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut raw: bool = false;
+        let mut file_path: Option<String> = None;
+        for arg in args {
+            match arg.as_str() {
+                "--raw" => raw = true,
+                flag if flag.starts_with("--") => return Err(format!("unknown flag '{}'", flag)),
+                path => {
+                    if file_path.is_some() {
+                        return Err(format!("unexpected argument '{}'", path));
+                    }
+                    file_path = Some(path.into());
+                }
+            }
+        }
+        Ok(Self {
+            file_path: file_path.ok_or("missing input file")?,
+            raw,
+        })
+    }
+}
+
+fn build_file(options: &BuildOptions) -> ExitCode {
+    let executable: Executable = match link_files(&options.input_paths) {
+        Ok(executable) => executable,
+        Err(err) => {
+            eprintln!("[ERROR] {}", err);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+    match fs::write(&options.output_path, executable.to_bytes()) {
+        Ok(_) => {
+            println!("[INFO] Wrote {}", options.output_path);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("[ERROR] couldn't write {} : {}", options.output_path, err);
+            ExitCode::from(EXIT_USAGE)
+        }
+    }
+}
+
+fn disassemble_file(options: &DisasmOptions) -> ExitCode {
+    match read_executable(&options.file_path, options.raw) {
+        Ok(executable) => {
+            print!("{}", disassembler::disassemble(&executable));
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("[ERROR] {}", err);
+            ExitCode::from(EXIT_USAGE)
+        }
+    }
+}
+
+fn run_file(options: &RunOptions) -> ExitCode {
+    let executable: Executable = match read_executable(&options.file_path, options.raw) {
+        Ok(executable) => executable,
+        Err(err) => {
+            eprintln!("[ERROR] {}", err);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    let mut vm: VM = VM::new();
+    vm.load(&executable);
+    vm.trace = options.trace;
+    let result: RunOutcome = match options.max_steps {
+        Some(max_steps) => vm.run_for(max_steps),
+        None => match vm.run() {
+            Ok(exit_reason) => RunOutcome::Halted(exit_reason),
+            Err(err) => RunOutcome::Faulted(err),
+        },
+    };
+
+    if options.dump_registers {
+        dump_registers(&vm);
+    }
+
+    match result {
+        RunOutcome::Halted(_) => ExitCode::SUCCESS,
+        RunOutcome::Preempted => {
+            eprintln!(
+                "[ERROR] program did not halt within {} steps (pc = {:#06X})",
+                options.max_steps.unwrap_or_default(),
+                vm.program_counter
+            );
+            ExitCode::from(EXIT_STEP_LIMIT)
+        }
+        RunOutcome::Faulted(err) => {
+            eprintln!("[ERROR] Runtime error : {}", err);
+            ExitCode::from(EXIT_FAULT)
+        }
+    }
+}
+
+fn dump_registers(vm: &VM) {
+    for (index, value) in vm.registers.iter().enumerate() {
+        println!("${:<2} = {}", index, value);
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() {
         println!("[INFO] Starting REPL");
         let vm: VM = VM::new();
         let mut cli: REPL = REPL::new(vm);
         cli.run();
-    } else {
-        println!("[INFO] running file on Spectrum vm")
+        return ExitCode::SUCCESS;
+    }
+
+    let result: Result<ExitCode, String> = match args[0].as_str() {
+        "build" => BuildOptions::from_args(&args[1..]).map(|options| build_file(&options)),
+        "disasm" => DisasmOptions::from_args(&args[1..]).map(|options| disassemble_file(&options)),
+        _ => RunOptions::from_args(&args).map(|options| run_file(&options)),
+    };
+    match result {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("[ERROR] {}", err);
+            eprintln!("{}", USAGE);
+            ExitCode::from(EXIT_USAGE)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_run_options() {
+        let options = RunOptions::from_args(&args(&["--trace", "prog.asm", "--max-steps", "20"]));
+        assert_eq!(
+            options,
+            Ok(RunOptions {
+                file_path: "prog.asm".into(),
+                dump_registers: false,
+                trace: true,
+                max_steps: Some(20),
+                raw: false,
+            })
+        )
+    }
+
+    #[test]
+    fn reject_bad_arguments() {
+        assert!(RunOptions::from_args(&args(&["--max-steps", "ten", "prog.asm"])).is_err());
+        assert!(RunOptions::from_args(&args(&["--dump-registers"])).is_err());
+        assert!(RunOptions::from_args(&args(&["--fast", "prog.asm"])).is_err());
+    }
+
+    #[test]
+    fn parse_build_options() {
+        assert_eq!(
+            BuildOptions::from_args(&args(&["prog.asm"])),
+            Ok(BuildOptions {
+                input_paths: vec!["prog.asm".into()],
+                output_path: "prog.bin".into(),
+            })
+        );
+        assert_eq!(
+            BuildOptions::from_args(&args(&["prog.asm", "lib/std.asm"])).map(|o| o.input_paths),
+            Ok(vec!["prog.asm".into(), "lib/std.asm".into()])
+        );
+        assert_eq!(
+            BuildOptions::from_args(&args(&["-o", "out", "prog.asm"])).map(|o| o.output_path),
+            Ok("out".into())
+        );
+        assert!(BuildOptions::from_args(&args(&["-o"])).is_err())
+    }
+
+    #[test]
+    fn parse_disasm_options() {
+        assert_eq!(
+            DisasmOptions::from_args(&args(&["--raw", "prog.bin"])),
+            Ok(DisasmOptions {
+                file_path: "prog.bin".into(),
+                raw: true,
+            })
+        );
+        assert!(DisasmOptions::from_args(&args(&["--trace", "prog.bin"])).is_err());
+        assert!(DisasmOptions::from_args(&args(&["--max-steps", "20", "prog.bin"])).is_err());
+        assert!(DisasmOptions::from_args(&args(&["--dump-registers", "prog.bin"])).is_err());
     }
 }
@@ -1 +1,3 @@
 pub mod cli;
+pub mod debugger;
+pub mod line_editor;
@@ -1,10 +1,62 @@
-use std::io::{self, Write};
+use std::{fs, io, mem};
 
-use crate::{assembler::{lexer::Lexer, parser::Parser, program::Program}, utils::hex_to_byte_arr, vm::VM};
+use crate::{
+    assembler::{
+        self,
+        lexer::PSEUDO_OPERATIONS,
+        program::Program,
+        symbols::{Section, SymbolTable},
+    },
+    disassembler::{disassemble, disassemble_instruction},
+    executable::Executable,
+    instruction::Opcode,
+    utils::hex_to_byte_arr,
+    vm::{error::ExitReason, VM},
+};
 
+use super::{
+    debugger::{current_instruction, parse_address, parse_register, stop_report, Debugger, Stop},
+    line_editor::LineEditor,
+};
+
+/// commands offered by tab completion
+const DOT_COMMANDS: [&str; 17] = [
+    ".help",
+    ".quit",
+    ".program",
+    ".disasm",
+    ".registers",
+    ".input_mode",
+    ".reset",
+    ".pc",
+    ".load",
+    ".save",
+    ".run",
+    ".history",
+    ".break",
+    ".step",
+    ".continue",
+    ".watch",
+    ".where",
+];
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct REPL {
     vm: VM,
     command_buffer: Vec<String>,
+    debugger: Debugger,
+    /// labels declared in the session, at their address in the vm bytecode or read-only data
+    labels: SymbolTable,
+    /// lines are read as hex encoded instructions instead of assembly
+    is_hex_input: bool,
+    /// the program executed HLT or faulted, .step and .continue need .pc or .reset first
+    halted: bool,
+    /// assembly source of the current program, written by .save to `.asm` files
+    source: Vec<String>,
+    /// where .run restarts the program, 0 unless a loaded file declares `main`
+    entry_point: usize,
+    /// set by .quit, the session ends once the line is handled
+    quit: bool,
 }
 
 impl REPL {
@@ -12,84 +64,555 @@ impl REPL {
         Self {
             vm,
             command_buffer: Vec::new(),
+            debugger: Debugger::new(),
+            labels: SymbolTable::new(),
+            is_hex_input: false,
+            halted: false,
+            source: Vec::new(),
+            entry_point: 0,
+            quit: false,
         }
     }
 
     pub fn run(&mut self) {
         println!("[INFO] Entering SPECTRUM");
+        let mut editor: LineEditor = LineEditor::new();
         loop {
-            let mut is_hex_input:bool = false;
-            let mut lexer: Lexer = Lexer::new("", "".len());
-            let mut parser: Parser = Parser::default();
-            let mut program: Program = Program::default();
-
-            let mut buffer: String = String::new();
-            print!("[REPL]>> ");
-            io::stdout()
-                .flush()
-                .expect("[REPL]>> [FATAL] Couldn't flush stdout");
-            io::stdin()
-                .read_line(&mut buffer)
+            let buffer: Option<String> = editor
+                .read_line("[REPL]>> ", |line| self.completions(line))
                 .expect("[REPL]>> [FATAL] Failed to read user input");
+            let buffer: String = match buffer {
+                Some(buffer) => buffer,
+                None => {
+                    println!();
+                    println!("[INFO] Shutting down SPECTRUM");
+                    return;
+                }
+            };
             let buffer: &str = buffer.trim();
+            if buffer.is_empty() {
+                continue;
+            }
             self.command_buffer.push(buffer.into());
-            match buffer {
-                ".quit" => {
-                    println!("[INFO] Shutting down SPECTRUM");
-                    std::process::exit(0);
-                },
-                ".help" => {
-                    println!("[REPL]>> .help : list all commands");
-                    println!("[REPL]>> .quit : exit current process");
-                    println!("[REPL]>> .program : display vm's current bytecode");
-                    println!("[REPL]>> .registers : display vm's registers state");
-                    println!("[REPL]>> .input_mode : switch input method (between INSTRUCTION and HEX)");
-                },
-                ".program" => {
-                    println!("[REPL]>> {:#?}", self.vm.bytecode)
+            self.execute(buffer);
+            if self.quit {
+                return;
+            }
+        }
+    }
+
+    /// words that can complete the last word of `line`: dot-commands and mnemonics first
+    /// on the line, label names after .break and .pc, `@label` references and mnemonics elsewhere
+    fn completions(&self, line: &str) -> Vec<String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let is_new_word: bool = line.is_empty() || line.ends_with(char::is_whitespace);
+        let (word, previous): (&str, Option<&str>) = if is_new_word {
+            ("", words.last().copied())
+        } else {
+            (
+                words[words.len() - 1],
+                words.len().checked_sub(2).map(|index| words[index]),
+            )
+        };
+        let labels = self.labels.iter().map(|symbol| symbol.name.clone());
+        let code_labels = self
+            .labels
+            .iter()
+            .filter(|symbol| symbol.section == Section::Code)
+            .map(|symbol| symbol.name.clone());
+        let mnemonics = (0..=u8::MAX)
+            .map(Opcode::from)
+            .take_while(|code| *code != Opcode::NOP)
+            .map(|code| format!("{:?}", code))
+            .chain(PSEUDO_OPERATIONS.iter().map(|name| name.to_string()));
+        match previous {
+            Some(".break" | ".pc") => code_labels.collect(),
+            Some(command) if command.starts_with('.') => Vec::new(),
+            None => DOT_COMMANDS
+                .iter()
+                .map(|command| command.to_string())
+                .chain(mnemonics)
+                .collect(),
+            _ if word.starts_with('@') => labels.map(|name| format!("@{}", name)).collect(),
+            _ => mnemonics.collect(),
+        }
+    }
+
+    /// handle one line of input, a dot-command or an instruction to append and run
+    fn execute(&mut self, buffer: &str) {
+        let mut words = buffer.split_whitespace();
+        let command: &str = words.next().unwrap_or_default();
+        let argument: Option<&str> = words.next();
+        match command {
+            ".quit" => {
+                println!("[INFO] Shutting down SPECTRUM");
+                self.quit = true;
+            }
+            ".help" => {
+                println!("[REPL]>> .help : list all commands");
+                println!("[REPL]>> .quit : exit current process");
+                println!("[REPL]>> .program : display vm's current bytecode");
+                println!("[REPL]>> .disasm : display vm's current bytecode as assembly");
+                println!("[REPL]>> .registers : display vm's registers state");
+                println!(
+                    "[REPL]>> .input_mode : switch input method (between INSTRUCTION and HEX)"
+                );
+                println!("[REPL]>> .reset : clear the program and the vm state");
+                println!("[REPL]>> .load <file.asm|file.bin> : replace the program with a source file or an executable");
+                println!("[REPL]>> .save <file> : write the program as assembly source to a .asm file, as an executable otherwise");
+                println!("[REPL]>> .run : restart the program with a clean vm state, from `main` if the loaded file has one, from 0 otherwise");
+                println!("[REPL]>> .history : display the lines entered in the session");
+                println!(
+                    "[REPL]>> .pc [addr|label] : display the pc and halt state, or move the pc"
+                );
+                println!("[REPL]>> .break <addr|label> : stop before the instruction at addr, lists breakpoints without argument");
+                println!("[REPL]>> .step [n] : execute n instructions (default 1)");
+                println!("[REPL]>> .continue : run until a breakpoint, a watched register change or the end of the program");
+                println!("[REPL]>> .watch $reg : stop when the register changes, lists watched registers without argument");
+                println!("[REPL]>> .where : display the current instruction");
+            }
+            ".reset" => {
+                self.reset();
+                println!("[REPL]>> [INFO] Program and vm state cleared");
+            }
+            ".load" => match argument {
+                Some(file_path) => self.load_file(file_path),
+                None => println!("[REPL]>> [WARNING] .load expects a file path"),
+            },
+            ".save" => match argument {
+                Some(file_path) => self.save_file(file_path),
+                None => println!("[REPL]>> [WARNING] .save expects a file path"),
+            },
+            ".run" => {
+                self.restart();
+                let previous: [i32; 32] = self.vm.registers;
+                let stop: Stop = self.debugger.resume(&mut self.vm);
+                self.report_stop(&stop, &previous);
+            }
+            ".history" => {
+                for (index, line) in self.command_buffer.iter().enumerate() {
+                    println!("[REPL]>> {:>3} {}", index + 1, line);
+                }
+            }
+            ".pc" => match argument {
+                None => self.print_where(),
+                Some(argument) => match parse_address(argument, &self.labels) {
+                    Some(address) if address <= self.vm.bytecode.len() => {
+                        self.vm.program_counter = address;
+                        self.halted = false;
+                        self.print_where();
+                    }
+                    Some(address) => println!(
+                        "[REPL]>> [WARNING] {:#06X} is past the end of the program",
+                        address
+                    ),
+                    None => println!("[REPL]>> [WARNING] Unknown address or label '{}'", argument),
                 },
-                ".registers" => {
-                    println!("[REPL]>> {:#?}", self.vm.registers)
+            },
+            ".break" => match argument {
+                None => {
+                    for address in self.debugger.breakpoints() {
+                        println!("[REPL]>> breakpoint at {:#06X}", address);
+                    }
+                }
+                Some(argument) => match parse_address(argument, &self.labels) {
+                    Some(address) => {
+                        self.debugger.add_breakpoint(address);
+                        println!("[REPL]>> breakpoint at {:#06X}", address);
+                    }
+                    None => println!("[REPL]>> [WARNING] Unknown address or label '{}'", argument),
                 },
-                ".input_mode" => {
-                    if !is_hex_input {
-                        is_hex_input = true;
-                        println!("[REPL]>> [INFO] Switching input method from INSTRUCTION to HEX");
-                    } else {
-                        is_hex_input = false;
-                        println!("[REPL]>> [INFO] Switching input method from HEX to INSTRUCTION");
+            },
+            ".step" | ".continue" if self.halted => {
+                println!("[REPL]>> [WARNING] The program halted, move the pc with .pc or start over with .reset");
+            }
+            ".step" => {
+                let count: Option<usize> = match argument {
+                    Some(argument) => argument.parse::<usize>().ok(),
+                    None => Some(1),
+                };
+                match count {
+                    Some(count) => {
+                        let previous: [i32; 32] = self.vm.registers;
+                        let stop: Stop = self.debugger.step(&mut self.vm, count);
+                        self.report_stop(&stop, &previous);
                     }
+                    None => println!("[REPL]>> [WARNING] .step expects a number of instructions"),
+                }
+            }
+            ".continue" => {
+                let previous: [i32; 32] = self.vm.registers;
+                let stop: Stop = self.debugger.resume(&mut self.vm);
+                self.report_stop(&stop, &previous);
+            }
+            ".watch" => match argument {
+                None => {
+                    for register in self.debugger.watches() {
+                        println!("[REPL]>> ${} = {}", register, self.vm.registers[*register]);
+                    }
+                }
+                Some(argument) => match parse_register(argument) {
+                    Some(register) => {
+                        self.debugger.add_watch(register);
+                        println!("[REPL]>> watching ${}", register);
+                    }
+                    None => println!("[REPL]>> [WARNING] .watch expects a register, e.g. $3"),
+                },
+            },
+            ".where" => self.print_where(),
+            ".program" => {
+                println!("[REPL]>> {:#?}", self.vm.bytecode)
+            }
+            ".disasm" => {
+                let executable: Executable = Executable {
+                    code: self.vm.bytecode.clone(),
+                    ro_data: self.vm.ro_data.clone(),
+                    ..Default::default()
+                };
+                for line in disassemble(&executable).lines() {
+                    println!("[REPL]>> {}", line);
                 }
-                _ => {
-                    if !is_hex_input {
-                        lexer.set_content(buffer);
-                        lexer.tokens = Vec::new();
-                        lexer.tokenize();
-                        parser.set_tokens(lexer.tokens);
-                        let parsing_result: Vec<_> = parser.parse();
-                        program.set_instructions(parsing_result);
-                        let program_as_bytes: Vec<u8> = program.as_bytes();
-                        for byte in program_as_bytes {
-                            self.vm.bytecode.push(byte);
+            }
+            ".registers" => {
+                println!("[REPL]>> {:#?}", self.vm.registers)
+            }
+            ".input_mode" => {
+                if !self.is_hex_input {
+                    self.is_hex_input = true;
+                    println!("[REPL]>> [INFO] Switching input method from INSTRUCTION to HEX");
+                } else {
+                    self.is_hex_input = false;
+                    println!("[REPL]>> [INFO] Switching input method from HEX to INSTRUCTION");
+                }
+            }
+            _ => {
+                let line_bytes: Option<Vec<u8>> = if !self.is_hex_input {
+                    self.assemble_line(buffer)
+                } else {
+                    match hex_to_byte_arr(buffer) {
+                        Ok(bytes) => {
+                            let text: String = disassemble_instruction(&bytes)
+                                .unwrap_or_else(|| format!("; {}", buffer));
+                            self.source.push(text);
+                            Some(bytes.to_vec())
                         }
-                        self.vm.run();
-                    } else {
-                        let parsed_instruction: Result<[u8; 4], _> = hex_to_byte_arr(buffer);
-                        match parsed_instruction {
-                            Ok(bytes) => {
-                                for byte in bytes.iter() {
-                                    self.vm.bytecode.push(*byte);
-                                }
-                                self.vm.run();
-                            }
-                            Err(_) => {
-                                println!("[REPL]>> [WARNING] Failed to parse instruction");
-                                println!("[REPL]>> [INFO] Correct format is \'00 00 00 00\'");
-                            }
+                        Err(err) => {
+                            println!("[REPL]>> [WARNING] Failed to parse instruction : {}", err);
+                            println!("[REPL]>> [INFO] Correct format is \'00 00 00 00\'");
+                            None
                         }
                     }
+                };
+                if let Some(line_bytes) = line_bytes {
+                    self.run_line(line_bytes);
                 }
             }
         }
     }
+
+    /// assemble a line of instructions placed at the end of the current bytecode,
+    /// its data is appended to the read-only data of the vm
+    fn assemble_line(&mut self, buffer: &str) -> Option<Vec<u8>> {
+        // macros only live for the line that defines them
+        let program: Program = match assembler::assemble_at(
+            buffer,
+            self.vm.bytecode.len(),
+            self.vm.ro_data.len(),
+            &self.labels,
+        ) {
+            Ok(program) => program,
+            Err(mut diagnostics) => {
+                diagnostics.file = Some("<repl>".into());
+                for line in diagnostics.render(buffer).lines() {
+                    println!("[REPL]>> {}", line);
+                }
+                return None;
+            }
+        };
+        match program
+            .as_bytes()
+            .and_then(|code| Ok((code, program.data_bytes()?)))
+        {
+            Ok((program_as_bytes, mut data)) => {
+                if let Ok(symbols) = program.symbols() {
+                    // the symbols start with the labels of the line, then those of the session
+                    // generated names (macro-local labels, float literals) only live for the line
+                    for symbol in symbols
+                        .iter()
+                        .take(symbols.len() - self.labels.len())
+                        .filter(|symbol| !symbol.name.contains('.'))
+                    {
+                        self.labels.add_symbol(symbol.clone());
+                    }
+                }
+                self.vm.ro_data.append(&mut data);
+                self.source.push(buffer.into());
+                Some(program_as_bytes)
+            }
+            Err(err) => {
+                println!("[REPL]>> [ERROR] {}", err);
+                None
+            }
+        }
+    }
+
+    /// clear the program and the vm state, the trace setting is kept
+    fn reset(&mut self) {
+        let trace: bool = self.vm.trace;
+        self.vm = VM::new();
+        self.vm.trace = trace;
+        self.labels = SymbolTable::new();
+        self.halted = false;
+        self.source = Vec::new();
+        self.entry_point = 0;
+    }
+
+    /// clear the vm state but keep the program, the pc is set to its entry point
+    fn restart(&mut self) {
+        let mut vm: VM = VM::new();
+        vm.trace = self.vm.trace;
+        vm.bytecode = mem::take(&mut self.vm.bytecode);
+        vm.ro_data = mem::take(&mut self.vm.ro_data);
+        vm.program_counter = self.entry_point;
+        self.vm = vm;
+        self.halted = false;
+    }
+
+    fn load_file(&mut self, file_path: &str) {
+        let executable: Executable = match crate::read_executable(file_path, false) {
+            Ok(executable) => executable,
+            Err(err) => {
+                println!("[REPL]>> [ERROR] {}", err);
+                return;
+            }
+        };
+        let source: String = if file_path.ends_with(".asm") {
+            fs::read_to_string(file_path).unwrap_or_default()
+        } else {
+            disassemble(&executable)
+        };
+        self.reset();
+        self.vm.load(&executable);
+        self.entry_point = executable.entry_point;
+        for symbol in executable.symbols.iter() {
+            self.labels.add_symbol(symbol.clone());
+        }
+        self.source = source.lines().map(String::from).collect();
+        println!(
+            "[REPL]>> [INFO] Loaded {} bytes of code from {}, .run to start at {:#06X}",
+            executable.code.len(),
+            file_path,
+            self.entry_point
+        );
+    }
+
+    fn save_file(&self, file_path: &str) {
+        let result: io::Result<()> = if file_path.ends_with(".asm") {
+            let mut content: String = self.source.join("\n");
+            content.push('\n');
+            fs::write(file_path, content)
+        } else {
+            let executable: Executable = Executable {
+                entry_point: self.entry_point,
+                code: self.vm.bytecode.clone(),
+                ro_data: self.vm.ro_data.clone(),
+                symbols: self.labels.clone(),
+                ..Default::default()
+            };
+            fs::write(file_path, executable.to_bytes())
+        };
+        match result {
+            Ok(()) => println!("[REPL]>> [INFO] Saved program to {}", file_path),
+            Err(err) => println!("[REPL]>> [ERROR] couldn't write {} : {}", file_path, err),
+        }
+    }
+
+    /// append the instructions of one input line and run them from their first byte,
+    /// stopping at breakpoints and watched registers
+    fn run_line(&mut self, line_bytes: Vec<u8>) {
+        self.vm.program_counter = self.vm.bytecode.len();
+        self.vm.bytecode.extend(line_bytes);
+        self.halted = false;
+        let previous: [i32; 32] = self.vm.registers;
+        match self.debugger.resume(&mut self.vm) {
+            Stop::Exited(ExitReason::EndOfProgram) => {}
+            stop => self.report_stop(&stop, &previous),
+        }
+    }
+
+    /// record whether the program halted and print why it stopped
+    fn report_stop(&mut self, stop: &Stop, previous: &[i32; 32]) {
+        self.halted = matches!(stop, Stop::Exited(ExitReason::Halted) | Stop::Fault(_));
+        for line in stop_report(&self.vm, stop, previous) {
+            println!("[REPL]>> {}", line);
+        }
+    }
+
+    fn print_where(&self) {
+        let state: &str = if self.halted { "halted" } else { "ready" };
+        println!("[REPL]>> {} ({})", current_instruction(&self.vm), state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn input_mode_persists() {
+        let mut repl: REPL = REPL::new(VM::new());
+        repl.execute(".input_mode");
+        repl.execute("01 01 01 F4");
+        repl.execute("01 02 00 07");
+        repl.execute("01 03 00 07 00");
+        repl.execute("01 03");
+        assert!(repl.is_hex_input);
+        assert_eq!(repl.vm.bytecode.len(), 8);
+        assert_eq!((repl.vm.registers[1], repl.vm.registers[2]), (500, 7))
+    }
+
+    #[test]
+    fn each_line_runs_its_own_instructions() {
+        let mut repl: REPL = REPL::new(VM::new());
+        repl.execute("DIV $0 $1 $2");
+        assert!(repl.halted);
+        assert_eq!(repl.vm.program_counter, 0);
+        repl.execute("LOAD $3 #3 INC $4");
+        assert!(!repl.halted);
+        assert_eq!((repl.vm.registers[3], repl.vm.registers[4]), (3, 1));
+        repl.execute("HLT");
+        assert!(repl.halted);
+        repl.execute("INC $4");
+        assert_eq!(repl.vm.registers[4], 2);
+        assert_eq!(repl.vm.program_counter, 20)
+    }
+
+    #[test]
+    fn move_pc_and_reset() {
+        let mut repl: REPL = REPL::new(VM::new());
+        repl.execute("INC $0 HLT");
+        repl.execute(".step");
+        assert_eq!(repl.vm.registers[0], 1);
+        repl.execute(".pc 0");
+        assert!(!repl.halted);
+        repl.execute(".continue");
+        assert_eq!(repl.vm.registers[0], 2);
+        repl.execute(".pc 0x10");
+        assert_eq!(repl.vm.program_counter, 8);
+        repl.execute(".reset");
+        assert!(repl.vm.bytecode.is_empty());
+        assert_eq!((repl.vm.program_counter, repl.vm.registers[0]), (0, 0))
+    }
+
+    #[test]
+    fn load_run_and_save() {
+        let directory = std::env::temp_dir();
+        let source_path: String = directory
+            .join("spectrum_repl_load.asm")
+            .display()
+            .to_string();
+        let binary_path: String = directory
+            .join("spectrum_repl_save.bin")
+            .display()
+            .to_string();
+        let saved_source_path: String = directory
+            .join("spectrum_repl_save.asm")
+            .display()
+            .to_string();
+        fs::write(&source_path, "INC $0\nmain: INC $1\nHLT\n").unwrap();
+
+        let mut repl: REPL = REPL::new(VM::new());
+        repl.execute(&format!(".load {}", source_path));
+        assert_eq!(repl.vm.program_counter, 4);
+        repl.execute(".run");
+        repl.execute(".run");
+        assert!(repl.halted);
+        assert_eq!((repl.vm.registers[0], repl.vm.registers[1]), (0, 1));
+        repl.execute("INC $2");
+        repl.execute(&format!(".save {}", binary_path));
+        repl.execute(&format!(".save {}", saved_source_path));
+        assert_eq!(
+            fs::read_to_string(&saved_source_path).unwrap(),
+            "INC $0\nmain: INC $1\nHLT\nINC $2\n"
+        );
+
+        let bytecode: Vec<u8> = repl.vm.bytecode.clone();
+        repl.execute(&format!(".load {}", binary_path));
+        assert_eq!(repl.vm.bytecode, bytecode);
+        assert_eq!(repl.entry_point, 4);
+        repl.execute(&format!(".load {}", saved_source_path));
+        assert_eq!(repl.vm.bytecode, bytecode);
+        for path in [source_path, binary_path, saved_source_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn run_starts_at_the_entry_point() {
+        let source_path: String = std::env::temp_dir()
+            .join("spectrum_repl_entry.asm")
+            .display()
+            .to_string();
+        fs::write(&source_path, "LOAD $0 #7\nINC $1\nmain: INC $2\nHLT\n").unwrap();
+
+        let mut repl: REPL = REPL::new(VM::new());
+        repl.execute(&format!(".load {}", source_path));
+        repl.execute(".run");
+        assert_eq!(repl.vm.registers[0..3], [0, 0, 1]);
+        fs::remove_file(source_path).unwrap();
+
+        repl.execute(".reset");
+        repl.execute("LOAD $0 #7");
+        repl.execute("INC $1");
+        repl.execute(".run");
+        assert_eq!(repl.vm.registers[0..2], [7, 1]);
+    }
+
+    #[test]
+    fn labels_at_their_session_address() {
+        let mut repl: REPL = REPL::new(VM::new());
+        repl.execute("INC $0");
+        repl.execute("top: INC $1 LA $2 @top");
+        assert_eq!(repl.vm.registers[2], 4);
+        assert_eq!(repl.labels.symbol_offset("top"), Some(4));
+        repl.execute("DEC $1 again: INC $3 BZ @again");
+        assert_eq!(repl.vm.registers[3], 1);
+        assert_eq!(repl.vm.bytecode[20..24], [70, 255, 248, 0]);
+        repl.execute("LA $4 @top");
+        assert_eq!(repl.vm.registers[4], 4);
+        repl.execute("BZ @again");
+        assert_eq!(repl.vm.bytecode[28..32], [70, 255, 240, 0]);
+        repl.execute("top: INC $5");
+        assert_eq!((repl.vm.bytecode.len(), repl.vm.registers[5]), (32, 0))
+    }
+
+    #[test]
+    fn data_of_each_line() {
+        let mut repl: REPL = REPL::new(VM::new());
+        repl.execute(".data msg: .asciiz \"hi\" .code LA $0 @msg LOADDB $1 $0");
+        repl.execute("LOADF %f0 #1.5");
+        repl.execute("LOADF %f1 #2.5 LA $2 @msg");
+        assert_eq!(repl.vm.registers[1..3], ['h' as i32, 0]);
+        assert_eq!(
+            (repl.vm.float_registers[0], repl.vm.float_registers[1]),
+            (1.5, 2.5)
+        );
+        assert_eq!(repl.vm.ro_data.len(), 19);
+        assert!(repl.labels.iter().all(|symbol| !symbol.name.contains('.')));
+        assert_eq!(repl.completions(".break "), Vec::<String>::new())
+    }
+
+    #[test]
+    fn complete_words() {
+        let mut repl: REPL = REPL::new(VM::new());
+        repl.execute("start: INC $0");
+        assert!(repl.completions(".re").contains(&".reset".to_string()));
+        assert!(repl.completions("").contains(&".help".to_string()));
+        let mnemonics: Vec<String> = repl.completions("INC $0 LO");
+        assert!(mnemonics.contains(&"LOADW".to_string()) && mnemonics.contains(&"LA".to_string()));
+        assert!(!mnemonics.contains(&"NOP".to_string()));
+        assert_eq!(repl.completions("LA $1 @s"), ["@start"]);
+        assert_eq!(repl.completions(".break "), ["start"]);
+        assert!(repl.completions(".load sp").is_empty())
+    }
 }
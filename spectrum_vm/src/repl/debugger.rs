@@ -0,0 +1,283 @@
+use crate::{
+    assembler::symbols::{Section, SymbolTable},
+    disassembler::disassemble_instruction,
+    vm::{
+        error::{ExitReason, VmError},
+        outcome::{RunOutcome, Step, StepOutcome},
+        INSTRUCTION_WIDTH, VM,
+    },
+};
+
+/// instructions executed by one `resume` before giving control back to the user,
+/// so that an infinite loop does not hang the REPL
+pub const RESUME_BUDGET: usize = 1_000_000;
+
+/// why the debugger gave control back to the user
+#[derive(Debug, PartialEq)]
+pub enum Stop {
+    /// the requested number of steps was executed, `last` is the final one
+    Stepped {
+        last: Step,
+    },
+    Breakpoint,
+    /// a watched register changed value
+    Watch {
+        register: usize,
+    },
+    /// `RESUME_BUDGET` instructions were executed without stopping
+    Preempted,
+    Exited(ExitReason),
+    Fault(VmError),
+}
+
+/// breakpoints and watched registers of a REPL session
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: Vec<usize>,
+    watches: Vec<usize>,
+    /// breakpoint the vm is stopped on, skipped when execution resumes
+    stopped_at: Option<usize>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_breakpoint(&mut self, address: usize) {
+        if !self.breakpoints.contains(&address) {
+            self.breakpoints.push(address);
+        }
+    }
+
+    pub fn breakpoints(&self) -> &[usize] {
+        &self.breakpoints
+    }
+
+    pub fn add_watch(&mut self, register: usize) {
+        if !self.watches.contains(&register) {
+            self.watches.push(register);
+        }
+    }
+
+    pub fn watches(&self) -> &[usize] {
+        &self.watches
+    }
+
+    /// execute `count` instructions, at least one, stopping early if the vm halts or faults
+    pub fn step(&mut self, vm: &mut VM, count: usize) -> Stop {
+        self.stopped_at = None;
+        let mut step: Step = vm.step();
+        for _ in 1..count {
+            if step.outcome != StepOutcome::Running {
+                break;
+            }
+            step = vm.step();
+        }
+        match step.outcome {
+            StepOutcome::Running => Stop::Stepped { last: step },
+            StepOutcome::Halted(exit_reason) => Stop::Exited(exit_reason),
+            StepOutcome::Faulted(err) => Stop::Fault(err),
+        }
+    }
+
+    /// run until a breakpoint is reached, a watched register changes, the vm stops
+    /// or `RESUME_BUDGET` instructions have been executed
+    /// breakpoints stop before their instruction executes, the one the vm is stopped
+    /// on is skipped so that execution can resume from it
+    pub fn resume(&mut self, vm: &mut VM) -> Stop {
+        let mut skip_breakpoint: bool = self.stopped_at.take() == Some(vm.program_counter);
+        let mut watched: Vec<i32> = self.watches.iter().map(|r| vm.registers[*r]).collect();
+        let mut stop: Option<Stop> = None;
+        let mut executed: usize = 0;
+        let outcome: RunOutcome = vm.run_until(|vm| {
+            for (register, value) in self.watches.iter().zip(&watched) {
+                if vm.registers[*register] != *value {
+                    stop = Some(Stop::Watch {
+                        register: *register,
+                    });
+                    return true;
+                }
+            }
+            let pc: usize = vm.program_counter;
+            if !skip_breakpoint && pc < vm.bytecode.len() && self.breakpoints.contains(&pc) {
+                stop = Some(Stop::Breakpoint);
+                return true;
+            }
+            if executed == RESUME_BUDGET {
+                stop = Some(Stop::Preempted);
+                return true;
+            }
+            executed += 1;
+            skip_breakpoint = false;
+            watched = self.watches.iter().map(|r| vm.registers[*r]).collect();
+            false
+        });
+        match outcome {
+            RunOutcome::Preempted => {
+                if stop == Some(Stop::Breakpoint) {
+                    self.stopped_at = Some(vm.program_counter);
+                }
+                stop.unwrap_or(Stop::Preempted)
+            }
+            RunOutcome::Halted(exit_reason) => Stop::Exited(exit_reason),
+            RunOutcome::Faulted(err) => Stop::Fault(err),
+        }
+    }
+}
+
+/// `0x` prefixed hexadecimal or decimal address, or a label known to the session
+pub fn parse_address(argument: &str, labels: &SymbolTable) -> Option<usize> {
+    if let Some(hex) = argument.strip_prefix("0x") {
+        return usize::from_str_radix(hex, 16).ok();
+    }
+    match argument.parse::<usize>() {
+        Ok(address) => Some(address),
+        Err(_) => labels
+            .iter()
+            .find(|symbol| symbol.name == argument && symbol.section == Section::Code)
+            .map(|symbol| symbol.offset),
+    }
+}
+
+/// `$n` register argument of `.watch`
+pub fn parse_register(argument: &str) -> Option<usize> {
+    let register: usize = argument.strip_prefix('$')?.parse::<usize>().ok()?;
+    (register < 32).then_some(register)
+}
+
+/// the instruction at the program counter, e.g. `0x0008: ADD $0 $1 $2`
+pub fn current_instruction(vm: &VM) -> String {
+    let pc: usize = vm.program_counter;
+    if pc >= vm.bytecode.len() {
+        return format!("{:#06X}: end of program", pc);
+    }
+    let end: usize = usize::min(pc + INSTRUCTION_WIDTH, vm.bytecode.len());
+    let text: String = disassemble_instruction(&vm.bytecode[pc..end]).unwrap_or_else(|| {
+        let bytes: Vec<String> = vm.bytecode[pc..end]
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        format!("??? {}", bytes.join(" "))
+    });
+    format!("{:#06X}: {}", pc, text)
+}
+
+/// lines describing a stop: its reason, the next instruction and the registers
+/// that changed since `previous`
+pub fn stop_report(vm: &VM, stop: &Stop, previous: &[i32; 32]) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    match stop {
+        Stop::Stepped { last } => {
+            let text: String = disassemble_instruction(&last.instruction).unwrap_or("???".into());
+            lines.push(format!("executed {:#06X}: {}", last.pc, text))
+        }
+        Stop::Breakpoint => lines.push("stopped at breakpoint".into()),
+        Stop::Preempted => lines.push(format!(
+            "preempted after {} instructions, .continue to resume",
+            RESUME_BUDGET
+        )),
+        Stop::Watch { register } => lines.push(format!("watched register ${} changed", register)),
+        Stop::Exited(ExitReason::Halted) => lines.push("program halted".into()),
+        Stop::Exited(ExitReason::EndOfProgram) => lines.push("reached end of program".into()),
+        Stop::Fault(err) => lines.push(format!("[ERROR] Runtime error : {}", err)),
+    }
+    lines.push(current_instruction(vm));
+    for (register, (value, old)) in vm.registers.iter().zip(previous).enumerate() {
+        if value != old {
+            lines.push(format!("${:<2} = {} (was {})", register, value, old));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use crate::assembler::symbols::Symbol;
+
+    use super::*;
+
+    fn loop_vm() -> VM {
+        let mut vm: VM = VM::new();
+        // INC $0, INC $1, JMP $31 with $31 = 0
+        vm.bytecode = vec![17, 0, 0, 0, 17, 1, 0, 0, 14, 31, 0, 0];
+        vm
+    }
+
+    #[test]
+    fn step_and_breakpoints() {
+        let mut vm: VM = loop_vm();
+        let mut debugger: Debugger = Debugger::new();
+        let stop: Stop = debugger.step(&mut vm, 2);
+        assert_eq!(vm.program_counter, 8);
+        assert_eq!(
+            stop_report(&vm, &stop, &[0; 32])[..2],
+            ["executed 0x0004: INC $1", "0x0008: JMP $31"]
+        );
+        debugger.add_breakpoint(4);
+        assert_eq!(debugger.resume(&mut vm), Stop::Breakpoint);
+        assert_eq!((vm.program_counter, vm.registers[0]), (4, 2));
+        // resuming from a breakpoint goes around the loop once
+        assert_eq!(debugger.resume(&mut vm), Stop::Breakpoint);
+        assert_eq!(vm.registers[1], 2);
+        // a breakpoint past the end of the bytecode waits for the instruction to exist
+        debugger.add_breakpoint(12);
+        vm.program_counter = 12;
+        assert_eq!(
+            debugger.resume(&mut vm),
+            Stop::Exited(ExitReason::EndOfProgram)
+        );
+        vm.bytecode.extend_from_slice(&[0, 0, 0, 0]);
+        assert_eq!(debugger.resume(&mut vm), Stop::Breakpoint);
+        assert_eq!(debugger.resume(&mut vm), Stop::Exited(ExitReason::Halted))
+    }
+
+    #[test]
+    fn watch_register() {
+        let mut vm: VM = loop_vm();
+        let mut debugger: Debugger = Debugger::new();
+        debugger.add_watch(1);
+        assert_eq!(debugger.resume(&mut vm), Stop::Watch { register: 1 });
+        assert_eq!(vm.program_counter, 8);
+        let previous: [i32; 32] = [0; 32];
+        assert_eq!(
+            stop_report(&vm, &Stop::Watch { register: 1 }, &previous),
+            [
+                "watched register $1 changed",
+                "0x0008: JMP $31",
+                "$0  = 1 (was 0)",
+                "$1  = 1 (was 0)"
+            ]
+        )
+    }
+
+    #[test]
+    fn preempt_infinite_loop() {
+        let mut vm: VM = loop_vm();
+        let mut debugger: Debugger = Debugger::new();
+        assert_eq!(debugger.resume(&mut vm), Stop::Preempted);
+        assert_eq!(vm.registers[0] + vm.registers[1], 666_667)
+    }
+
+    #[test]
+    fn stop_on_fault() {
+        let mut vm: VM = VM::new();
+        vm.bytecode = vec![5, 0, 1, 2];
+        let mut debugger: Debugger = Debugger::new();
+        assert!(matches!(debugger.step(&mut vm, 3), Stop::Fault(_)));
+        assert_eq!(current_instruction(&vm), "0x0000: DIV $0 $1 $2")
+    }
+
+    #[test]
+    fn parse_arguments() {
+        let mut labels: SymbolTable = SymbolTable::new();
+        labels.add_symbol(Symbol::new("loop", 12, Section::Code));
+        assert_eq!(parse_address("0x10", &labels), Some(16));
+        assert_eq!(parse_address("8", &labels), Some(8));
+        assert_eq!(parse_address("loop", &labels), Some(12));
+        assert_eq!(parse_address("nope", &labels), None);
+        assert_eq!(parse_register("$31"), Some(31));
+        assert_eq!(parse_register("$32"), None);
+        assert_eq!(parse_register("3"), None)
+    }
+}
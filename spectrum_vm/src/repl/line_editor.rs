@@ -0,0 +1,553 @@
+use std::{
+    env, fs,
+    io::{self, IsTerminal, Read, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+/// file in the home directory where entered lines are kept between sessions
+const HISTORY_FILE: &str = ".spectrum_history";
+/// number of lines kept in the history
+const HISTORY_LEN: usize = 1000;
+
+/// a key press decoded from terminal input
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    /// ctrl-u, delete up to the start of the line
+    KillStart,
+    /// ctrl-k, delete up to the end of the line
+    KillEnd,
+    /// ctrl-w, delete the word before the cursor
+    KillWord,
+    /// ctrl-c, abandon the line
+    Cancel,
+    /// ctrl-d, end of input on an empty line
+    Eof,
+    Unknown,
+}
+
+/// settings of the terminal saved once per session, done through `stty`
+/// so that no platform bindings are needed
+struct Terminal {
+    saved: String,
+}
+
+impl Terminal {
+    fn detect() -> Option<Self> {
+        if !io::stdin().is_terminal() {
+            return None;
+        }
+        let saved = Command::new("stty")
+            .arg("-g")
+            .stdin(Stdio::inherit())
+            .output()
+            .ok()?;
+        if !saved.status.success() {
+            return None;
+        }
+        let saved: String = String::from_utf8(saved.stdout).ok()?.trim().into();
+        Some(Self { saved })
+    }
+
+    /// switch to unbuffered input without echo while a line is edited, so that
+    /// the commands and programs it runs read the terminal in its usual mode
+    fn raw_mode(&self) -> Option<RawMode<'_>> {
+        let status = Command::new("stty")
+            .args(["-icanon", "-echo", "-isig", "min", "1"])
+            .stdin(Stdio::inherit())
+            .status()
+            .ok()?;
+        status.success().then_some(RawMode { terminal: self })
+    }
+}
+
+/// terminal in raw mode, the saved settings are restored on drop
+struct RawMode<'a> {
+    terminal: &'a Terminal,
+}
+
+impl Drop for RawMode<'_> {
+    fn drop(&mut self) {
+        let _ = Command::new("stty")
+            .arg(&self.terminal.saved)
+            .stdin(Stdio::inherit())
+            .status();
+    }
+}
+
+/// line being edited, the cursor is an index in chars
+#[derive(Debug, Default)]
+struct LineState {
+    line: Vec<char>,
+    cursor: usize,
+}
+
+impl LineState {
+    fn text(&self) -> String {
+        self.line.iter().collect()
+    }
+
+    fn before_cursor(&self) -> String {
+        self.line[..self.cursor].iter().collect()
+    }
+
+    fn insert(&mut self, text: &str) {
+        for c in text.chars() {
+            self.line.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+    }
+
+    /// replace the whole line, the cursor goes to its end
+    fn set(&mut self, text: &str) {
+        self.line = text.chars().collect();
+        self.cursor = self.line.len();
+    }
+}
+
+/// readline-style line input: cursor movement, history browsing and tab completion
+/// falls back to plain line reads when stdin is not a terminal
+pub struct LineEditor {
+    history: Vec<String>,
+    history_path: Option<PathBuf>,
+    /// None when stdin is not a terminal
+    terminal: Option<Terminal>,
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineEditor {
+    /// editor with the history of previous sessions, read from the home directory
+    pub fn new() -> Self {
+        let history_path: Option<PathBuf> = env::var_os("HOME")
+            .or_else(|| env::var_os("USERPROFILE"))
+            .map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        let mut history: Vec<String> = match &history_path {
+            Some(path) => fs::read_to_string(path)
+                .unwrap_or_default()
+                .lines()
+                .map(String::from)
+                .collect(),
+            None => Vec::new(),
+        };
+        let excess: usize = history.len().saturating_sub(HISTORY_LEN);
+        history.drain(..excess);
+        Self {
+            history,
+            history_path,
+            terminal: Terminal::detect(),
+        }
+    }
+
+    /// read one line after printing `prompt`, returns None at the end of input
+    /// `complete` gets the text before the cursor and returns the words that can
+    /// take the place of its last word
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        complete: impl Fn(&str) -> Vec<String>,
+    ) -> io::Result<Option<String>> {
+        let raw_mode: Option<RawMode> = self.terminal.as_ref().and_then(Terminal::raw_mode);
+        if raw_mode.is_none() {
+            print!("{}", prompt);
+            io::stdout().flush()?;
+            let mut buffer: String = String::new();
+            return match io::stdin().read_line(&mut buffer)? {
+                0 => Ok(None),
+                _ => Ok(Some(buffer.trim_end_matches(['\n', '\r']).into())),
+            };
+        }
+        let line: Option<String> =
+            self.edit(prompt, &mut io::stdin().lock(), &mut io::stdout(), complete)?;
+        drop(raw_mode);
+        if let Some(line) = &line {
+            self.add_history(line);
+        }
+        Ok(line)
+    }
+
+    fn add_history(&mut self, line: &str) {
+        let line: &str = line.trim();
+        if line.is_empty() || self.history.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.history.push(line.into());
+        if self.history.len() > HISTORY_LEN {
+            self.history.remove(0);
+        }
+        if let Some(path) = &self.history_path {
+            // the file is rewritten so it never holds more than HISTORY_LEN lines,
+            // losing the history is not worth interrupting the session
+            let mut content: String = self.history.join("\n");
+            content.push('\n');
+            let _ = fs::write(path, content);
+        }
+    }
+
+    fn edit(
+        &self,
+        prompt: &str,
+        input: &mut impl Read,
+        output: &mut impl Write,
+        complete: impl Fn(&str) -> Vec<String>,
+    ) -> io::Result<Option<String>> {
+        let mut state: LineState = LineState::default();
+        let mut history_index: usize = self.history.len();
+        // line being typed, kept while browsing the history
+        let mut draft: String = String::new();
+        redraw(output, prompt, &state)?;
+        loop {
+            let key: Key = match read_key(input)? {
+                Some(key) => key,
+                None if state.line.is_empty() => return Ok(None),
+                None => return Ok(Some(state.text())),
+            };
+            match key {
+                Key::Enter => {
+                    write!(output, "\r\n")?;
+                    return Ok(Some(state.text()));
+                }
+                Key::Eof if state.line.is_empty() => {
+                    write!(output, "\r\n")?;
+                    return Ok(None);
+                }
+                Key::Eof | Key::Delete => {
+                    if state.cursor < state.line.len() {
+                        state.line.remove(state.cursor);
+                    }
+                }
+                Key::Cancel => {
+                    write!(output, "^C\r\n")?;
+                    state = LineState::default();
+                    history_index = self.history.len();
+                }
+                Key::Char(c) => state.insert(c.encode_utf8(&mut [0; 4])),
+                Key::Tab => {
+                    let candidates: Vec<String> = complete(&state.before_cursor());
+                    if let Some(choices) = complete_word(&mut state, &candidates) {
+                        write!(output, "\r\n{}\r\n", choices.join("  "))?;
+                    }
+                }
+                Key::Backspace => {
+                    if state.cursor > 0 {
+                        state.cursor -= 1;
+                        state.line.remove(state.cursor);
+                    }
+                }
+                Key::Left => state.cursor = state.cursor.saturating_sub(1),
+                Key::Right => state.cursor = usize::min(state.cursor + 1, state.line.len()),
+                Key::Home => state.cursor = 0,
+                Key::End => state.cursor = state.line.len(),
+                Key::Up => {
+                    if history_index > 0 {
+                        if history_index == self.history.len() {
+                            draft = state.text();
+                        }
+                        history_index -= 1;
+                        state.set(&self.history[history_index]);
+                    }
+                }
+                Key::Down => {
+                    if history_index < self.history.len() {
+                        history_index += 1;
+                        match self.history.get(history_index) {
+                            Some(line) => state.set(line),
+                            None => state.set(&draft),
+                        }
+                    }
+                }
+                Key::KillStart => {
+                    state.line.drain(..state.cursor);
+                    state.cursor = 0;
+                }
+                Key::KillEnd => state.line.truncate(state.cursor),
+                Key::KillWord => {
+                    let mut start: usize = state.cursor;
+                    while start > 0 && state.line[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+                    while start > 0 && !state.line[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+                    state.line.drain(start..state.cursor);
+                    state.cursor = start;
+                }
+                Key::Unknown => {}
+            }
+            redraw(output, prompt, &state)?;
+        }
+    }
+}
+
+/// rewrite the prompt and the line, then put the terminal cursor back on the edit cursor
+fn redraw(output: &mut impl Write, prompt: &str, state: &LineState) -> io::Result<()> {
+    write!(output, "\r{}{}\x1b[K", prompt, state.text())?;
+    let back: usize = state.line.len() - state.cursor;
+    if back > 0 {
+        write!(output, "\x1b[{}D", back)?;
+    }
+    output.flush()
+}
+
+/// complete the word before the cursor with the candidates it prefixes, to the
+/// candidate followed by a space when there is only one, to their common prefix
+/// otherwise; returns the candidates to list when the word could not be extended
+fn complete_word(state: &mut LineState, candidates: &[String]) -> Option<Vec<String>> {
+    let mut start: usize = state.cursor;
+    while start > 0 && !state.line[start - 1].is_whitespace() {
+        start -= 1;
+    }
+    let word: String = state.line[start..state.cursor].iter().collect();
+    let matches: Vec<&String> = candidates
+        .iter()
+        .filter(|candidate| candidate.starts_with(&word))
+        .collect();
+    match matches[..] {
+        [] => None,
+        [single] => {
+            state.insert(&single[word.len()..]);
+            if state
+                .line
+                .get(state.cursor)
+                .is_none_or(|c| !c.is_whitespace())
+            {
+                state.insert(" ");
+            }
+            None
+        }
+        _ => {
+            let mut prefix: &str = matches[0];
+            for candidate in &matches[1..] {
+                while !candidate.starts_with(prefix) {
+                    prefix = &prefix[..prefix.len() - prefix.chars().last()?.len_utf8()];
+                }
+            }
+            if prefix.len() > word.len() {
+                state.insert(&prefix[word.len()..]);
+                return None;
+            }
+            let mut choices: Vec<String> = matches.into_iter().cloned().collect();
+            choices.sort();
+            choices.dedup();
+            Some(choices)
+        }
+    }
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte: [u8; 1] = [0];
+    match input.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+/// decode one key press, returns None at the end of input
+fn read_key(input: &mut impl Read) -> io::Result<Option<Key>> {
+    let byte: u8 = match read_byte(input)? {
+        Some(byte) => byte,
+        None => return Ok(None),
+    };
+    let key: Key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        8 | 127 => Key::Backspace,
+        1 => Key::Home,
+        2 => Key::Left,
+        3 => Key::Cancel,
+        4 => Key::Eof,
+        5 => Key::End,
+        6 => Key::Right,
+        11 => Key::KillEnd,
+        14 => Key::Down,
+        16 => Key::Up,
+        21 => Key::KillStart,
+        23 => Key::KillWord,
+        0x1b => read_escape_sequence(input)?,
+        0x20..=0x7e => Key::Char(byte as char),
+        0xc0..=0xf7 => {
+            // multi-byte utf-8 character, its length is given by the leading byte
+            let len: usize = match byte {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            };
+            let mut bytes: Vec<u8> = vec![byte];
+            for _ in 1..len {
+                bytes.extend(read_byte(input)?);
+            }
+            match std::str::from_utf8(&bytes)
+                .ok()
+                .and_then(|s| s.chars().next())
+            {
+                Some(c) => Key::Char(c),
+                None => Key::Unknown,
+            }
+        }
+        _ => Key::Unknown,
+    };
+    Ok(Some(key))
+}
+
+/// arrows, home, end and delete, sent as `ESC [ x`, `ESC O x` or `ESC [ n ~`
+fn read_escape_sequence(input: &mut impl Read) -> io::Result<Key> {
+    if !matches!(read_byte(input)?, Some(b'[' | b'O')) {
+        return Ok(Key::Unknown);
+    }
+    let key: Key = match read_byte(input)? {
+        Some(b'A') => Key::Up,
+        Some(b'B') => Key::Down,
+        Some(b'C') => Key::Right,
+        Some(b'D') => Key::Left,
+        Some(b'H') => Key::Home,
+        Some(b'F') => Key::End,
+        Some(digit @ b'0'..=b'9') => {
+            let mut code: u32 = (digit - b'0') as u32;
+            loop {
+                match read_byte(input)? {
+                    Some(b'~') => break,
+                    Some(digit @ b'0'..=b'9') => code = code * 10 + (digit - b'0') as u32,
+                    _ => return Ok(Key::Unknown),
+                }
+            }
+            match code {
+                1 | 7 => Key::Home,
+                3 => Key::Delete,
+                4 | 8 => Key::End,
+                _ => Key::Unknown,
+            }
+        }
+        _ => Key::Unknown,
+    };
+    Ok(key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn editor(history: &[&str]) -> LineEditor {
+        LineEditor {
+            history: history.iter().map(|line| line.to_string()).collect(),
+            history_path: None,
+            terminal: None,
+        }
+    }
+
+    fn type_keys(editor: &LineEditor, keys: &str, candidates: &[&str]) -> Option<String> {
+        let mut output: Vec<u8> = Vec::new();
+        let candidates: Vec<String> = candidates.iter().map(|c| c.to_string()).collect();
+        editor
+            .edit("> ", &mut keys.as_bytes(), &mut output, |_| {
+                candidates.clone()
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn decode_keys() {
+        let mut input: &[u8] = b"a\x1b[D\x1b[3~\x1bOH\x7f\r\xc3\xa9";
+        let mut keys: Vec<Key> = Vec::new();
+        while let Some(key) = read_key(&mut input).unwrap() {
+            keys.push(key);
+        }
+        assert_eq!(
+            keys,
+            [
+                Key::Char('a'),
+                Key::Left,
+                Key::Delete,
+                Key::Home,
+                Key::Backspace,
+                Key::Enter,
+                Key::Char('é')
+            ]
+        )
+    }
+
+    #[test]
+    fn edit_line() {
+        let editor: LineEditor = editor(&[]);
+        // type, move left twice, insert, delete backward, jump home and insert
+        let line = type_keys(&editor, "LOAD $1 #50\x1b[D\x1b[D5\x7f6\x01X\r", &[]);
+        assert_eq!(line, Some("XLOAD $1 #650".into()));
+        let line = type_keys(&editor, "INC $0 HLT\x17\x17$3\r", &[]);
+        assert_eq!(line, Some("INC $3".into()));
+        let line = type_keys(&editor, "HLT\x01\x0b\r", &[]);
+        assert_eq!(line, Some("".into()));
+        assert_eq!(type_keys(&editor, "\x04", &[]), None);
+        assert_eq!(
+            type_keys(&editor, "HLT\x03INC $0\r", &[]),
+            Some("INC $0".into())
+        )
+    }
+
+    #[test]
+    fn browse_history() {
+        let editor: LineEditor = editor(&["LOAD $1 #500", "ADD $0 $1 $2"]);
+        assert_eq!(
+            type_keys(&editor, "\x1b[A\x1b[A\r", &[]),
+            Some("LOAD $1 #500".into())
+        );
+        assert_eq!(
+            type_keys(&editor, "HL\x1b[A\x1b[A\x1b[B\x1b[BT\r", &[]),
+            Some("HLT".into())
+        )
+    }
+
+    #[test]
+    fn tab_completion() {
+        let editor: LineEditor = editor(&[]);
+        let candidates: [&str; 4] = ["LOAD", "LOADB", "LOADW", "LA"];
+        assert_eq!(
+            type_keys(&editor, "LOADW\t$1\r", &candidates),
+            Some("LOADW $1".into())
+        );
+        // completes to the common prefix, then lists the choices
+        assert_eq!(
+            type_keys(&editor, "LO\t\t $1\r", &candidates),
+            Some("LOAD $1".into())
+        );
+        let mut state: LineState = LineState::default();
+        state.set("LOAD");
+        let choices = complete_word(&mut state, &candidates.map(String::from));
+        assert_eq!(
+            choices,
+            Some(vec!["LOAD".into(), "LOADB".into(), "LOADW".into()])
+        );
+        assert_eq!(state.text(), "LOAD")
+    }
+
+    #[test]
+    fn history_file_is_bounded() {
+        let path: PathBuf = env::temp_dir().join("spectrum_history_test");
+        let mut editor: LineEditor = editor(&[]);
+        editor.history_path = Some(path.clone());
+        for index in 0..HISTORY_LEN + 5 {
+            editor.add_history(&format!("INC ${}", index % 32));
+            editor.add_history(&format!("line {}", index));
+        }
+        let content: String = fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), HISTORY_LEN);
+        assert_eq!(
+            content.lines().last(),
+            Some(format!("line {}", HISTORY_LEN + 4).as_str())
+        );
+        fs::remove_file(path).unwrap()
+    }
+}
@@ -1,19 +1,56 @@
-use std::num::ParseIntError;
-
 /// parse 32bits hex chunks
-/// hexes format is "00 00 00 00"
-pub fn hex_to_byte_arr(hexes: &str) -> Result<[u8; 4], ParseIntError> {
+/// hexes format is "00 00 00 00", exactly one instruction
+pub fn hex_to_byte_arr(hexes: &str) -> Result<[u8; 4], String> {
     let mut byte_array: [u8; 4] = [0; 4];
-    let hexes: Vec<&str> = hexes.split(" ").collect();
-    let mut index = 0;
-    for hex in &hexes {
+    let hexes: Vec<&str> = hexes.split_whitespace().collect();
+    if hexes.len() != byte_array.len() {
+        return Err(format!("expected 4 bytes, got {}", hexes.len()));
+    }
+    for (index, hex) in hexes.iter().enumerate() {
         match u8::from_str_radix(hex, 16) {
-            Ok(value) => {
-                byte_array[index] = value;
-                index += 1;
-            }
-            Err(err) => return Err(err),
+            Ok(value) => byte_array[index] = value,
+            Err(_) => return Err(format!("invalid hex byte '{}'", hex)),
         }
     }
     Ok(byte_array)
 }
+
+/// crc32 (IEEE 802.3 polynomial, as used by zip and png)
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask: u32 = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hex_chunks() {
+        assert_eq!(hex_to_byte_arr("01 01 01 F4"), Ok([1, 1, 1, 244]));
+        assert_eq!(
+            hex_to_byte_arr("01 zz 00 00"),
+            Err("invalid hex byte 'zz'".into())
+        );
+        assert_eq!(
+            hex_to_byte_arr("01 02 03 04 05"),
+            Err("expected 4 bytes, got 5".into())
+        );
+        assert_eq!(
+            hex_to_byte_arr("01 02"),
+            Err("expected 4 bytes, got 2".into())
+        )
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926)
+    }
+}
@@ -1,201 +1,762 @@
-use crate::instruction::Opcode;
+use std::collections::HashMap;
+
+use crate::{
+    assembler::{diagnostics::Diagnostics, program::Program},
+    disassembler::disassemble_instruction,
+    executable::{Executable, LoadError},
+    instruction::Opcode,
+};
+
+use self::{
+    error::{ExitReason, VmError},
+    flags::{Condition, Flags},
+    outcome::{RunOutcome, Step, StepOutcome},
+    syscall::{Console, Syscall, SyscallContext},
+};
+
+pub mod error;
+pub mod flags;
+pub mod outcome;
+pub mod syscall;
+
+/// size in bytes of every encoded instruction
+pub const INSTRUCTION_WIDTH: usize = 4;
+
+/// number of integer registers, and of float registers
+pub const REGISTER_COUNT: usize = 32;
 
 pub struct VM {
-    pub registers: [i32; 32],
+    pub registers: [i32; REGISTER_COUNT],
+    /// `%f0` to `%f31`, read and written by the float instructions
+    pub float_registers: [f64; REGISTER_COUNT],
     pub bytecode: Vec<u8>,
+    /// read-only data segment, read by LOADDB and LOADDW
+    pub ro_data: Vec<u8>,
     pub stack: [u8; 1024],
+    /// offset of the next free byte in `stack`, the stack grows upward by 32-bit words
+    pub stack_pointer: usize,
+    /// memory sized by ALOC, read and written by LOADB, LOADW, STOREB and STOREW
     pub heap: Vec<u8>,
     pub program_counter: usize,
     pub div_remainder: u32,
-    pub eq_flag: bool,
+    /// set by CMP, the relation tests and the arithmetic instructions,
+    /// read by the conditional jumps, branches and moves
+    pub flags: Flags,
+    /// print every executed instruction to stderr
+    pub trace: bool,
+    /// pc of the instruction being executed, used to report faults
+    instruction_start: usize,
+    /// host functions run by SYSCALL, by number
+    syscalls: HashMap<u16, Syscall>,
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VM {
+    /// vm with the built-in system calls printing to stdout and reading from stdin
     pub fn new() -> Self {
-        Self {
-            registers: [0; 32],
+        let mut vm: VM = Self {
+            registers: [0; REGISTER_COUNT],
+            float_registers: [0.0; REGISTER_COUNT],
             bytecode: Vec::new(),
+            ro_data: Vec::new(),
             stack: [0; 1024],
+            stack_pointer: 0,
             heap: Vec::new(),
             program_counter: 0,
             div_remainder: 0,
-            eq_flag: false,
+            flags: Flags::default(),
+            trace: false,
+            instruction_start: 0,
+            syscalls: HashMap::new(),
+        };
+        vm.set_console(Console::stdio());
+        vm
+    }
+
+    /// register `syscall` as system call `number`, replacing any previous one
+    pub fn register_syscall(
+        &mut self,
+        number: u16,
+        syscall: impl FnMut(&mut SyscallContext) -> Result<(), String> + 'static,
+    ) {
+        self.syscalls.insert(number, Box::new(syscall));
+    }
+
+    /// make the built-in system calls read from and print to `console`
+    pub fn set_console(&mut self, console: Console) {
+        self.syscalls.extend(syscall::builtins(console));
+    }
+
+    /// validate a Spectrum executable and load its code and data, the pc is set to its entry point
+    pub fn load_executable(&mut self, bytes: &[u8]) -> Result<Executable, LoadError> {
+        let executable: Executable = Executable::from_bytes(bytes)?;
+        self.load(&executable);
+        Ok(executable)
+    }
+
+    /// load the code and data of an already validated executable, the pc is set to its entry point
+    /// the registers, flags, stack and heap are cleared, the system calls and trace setting are kept
+    pub fn load(&mut self, executable: &Executable) {
+        self.registers = [0; REGISTER_COUNT];
+        self.float_registers = [0.0; REGISTER_COUNT];
+        self.stack = [0; 1024];
+        self.stack_pointer = 0;
+        self.heap = Vec::new();
+        self.div_remainder = 0;
+        self.flags = Flags::default();
+        self.instruction_start = 0;
+        self.bytecode = executable.code.clone();
+        self.ro_data = executable.ro_data.clone();
+        self.program_counter = executable.entry_point;
+    }
+
+    /// link an assembled program on its own and load it, the pc is set to its entry point
+    pub fn load_program(&mut self, program: &Program) -> Result<(), Diagnostics> {
+        let executable: Executable = program
+            .to_executable()
+            .map_err(|err| Diagnostics::new(vec![err]))?;
+        self.load(&executable);
+        Ok(())
+    }
+
+    /// run until the program halts or faults, never returns on an infinite loop
+    /// use `run_for` or `run_until` to execute untrusted programs
+    pub fn run(&mut self) -> Result<ExitReason, VmError> {
+        loop {
+            match self.step().outcome {
+                StepOutcome::Running => {}
+                StepOutcome::Halted(exit_reason) => return Ok(exit_reason),
+                StepOutcome::Faulted(err) => return Err(err),
+            }
+        }
+    }
+
+    /// run until the program halts or faults, or until `max_instructions` have been executed
+    pub fn run_for(&mut self, max_instructions: usize) -> RunOutcome {
+        let mut executed: usize = 0;
+        self.run_until(|_| {
+            executed += 1;
+            executed > max_instructions
+        })
+    }
+
+    /// run until the program halts or faults, or until `predicate` returns true
+    /// the predicate is called before each instruction with the vm about to execute it
+    pub fn run_until(&mut self, mut predicate: impl FnMut(&VM) -> bool) -> RunOutcome {
+        loop {
+            if predicate(self) {
+                return RunOutcome::Preempted;
+            }
+            match self.step().outcome {
+                StepOutcome::Running => {}
+                StepOutcome::Halted(exit_reason) => return RunOutcome::Halted(exit_reason),
+                StepOutcome::Faulted(err) => return RunOutcome::Faulted(err),
+            }
         }
     }
 
-    pub fn run(&mut self) {
-        let mut is_running = true;
-        while is_running {
-            is_running = self.execute_bytecode()
+    /// execute the instruction at the program counter
+    pub fn step(&mut self) -> Step {
+        let pc: usize = self.program_counter;
+        let end: usize = usize::min(pc.saturating_add(INSTRUCTION_WIDTH), self.bytecode.len());
+        let instruction: Vec<u8> = self.bytecode.get(pc..end).unwrap_or(&[]).to_vec();
+        let outcome: StepOutcome = match self.execute_bytecode() {
+            Ok(None) => StepOutcome::Running,
+            Ok(Some(exit_reason)) => StepOutcome::Halted(exit_reason),
+            Err(err) => {
+                self.program_counter = self.instruction_start;
+                StepOutcome::Faulted(err)
+            }
+        };
+        Step {
+            pc,
+            instruction,
+            outcome,
         }
     }
 
-    fn execute_bytecode(&mut self) -> bool {
-        if self.program_counter >= self.bytecode.len() {
-            return false;
+    fn execute_bytecode(&mut self) -> Result<Option<ExitReason>, VmError> {
+        self.instruction_start = self.program_counter;
+        if self.program_counter == self.bytecode.len() {
+            return Ok(Some(ExitReason::EndOfProgram));
+        }
+        if self.program_counter > self.bytecode.len() {
+            return Err(VmError::PcOutOfBounds {
+                pc: self.program_counter,
+                bytecode_len: self.bytecode.len(),
+            });
+        }
+        if self.program_counter + INSTRUCTION_WIDTH > self.bytecode.len() {
+            return Err(
+                self.fault(|pc, instruction| VmError::TruncatedInstruction { pc, instruction })
+            );
         }
 
-        match self.get_instruction_from_bytecode() {
+        if self.trace {
+            self.trace_instruction();
+        }
+
+        match self.get_instruction_from_bytecode()? {
             Opcode::LOAD => {
-                let register: usize = self.get_next_8_bits() as usize;
-                let value: usize = self.get_next_16_bits() as usize;
-                self.registers[register as usize] = value as i32;
-            }
-            Opcode::ADD => {
-                let operand_1: i32 = self.registers[self.get_next_8_bits() as usize];
-                let operand_2: i32 = self.registers[self.get_next_8_bits() as usize];
-                let register: usize = self.get_next_8_bits() as usize;
-                self.registers[register] = operand_1 + operand_2;
-            }
-            Opcode::SUB => {
-                let operand_1: i32 = self.registers[self.get_next_8_bits() as usize];
-                let operand_2: i32 = self.registers[self.get_next_8_bits() as usize];
-                let register: usize = self.get_next_8_bits() as usize;
-                self.registers[register] = operand_1 - operand_2;
-            }
-            Opcode::MUL => {
-                let operand_1: i32 = self.registers[self.get_next_8_bits() as usize];
-                let operand_2: i32 = self.registers[self.get_next_8_bits() as usize];
-                let register: usize = self.get_next_8_bits() as usize;
-                self.registers[register] = operand_1 * operand_2;
+                let register: usize = self.get_next_register()?;
+                let value: i16 = self.get_next_16_bits()? as i16;
+                self.registers[register] = value as i32;
             }
+            Opcode::ADD => self.arithmetic(Flags::add, false)?,
+            Opcode::SUB => self.arithmetic(Flags::sub, false)?,
+            Opcode::MUL => self.arithmetic(Flags::mul, false)?,
+            Opcode::ADDO => self.arithmetic(Flags::add, true)?,
+            Opcode::SUBO => self.arithmetic(Flags::sub, true)?,
+            Opcode::MULO => self.arithmetic(Flags::mul, true)?,
             Opcode::DIV => {
-                let operand_1: i32 = self.registers[self.get_next_8_bits() as usize];
-                let operand_2: i32 = self.registers[self.get_next_8_bits() as usize];
-                let register: usize = self.get_next_8_bits() as usize;
+                let operand_1: i32 = self.registers[self.get_next_register()?];
+                let operand_2: i32 = self.registers[self.get_next_register()?];
+                let register: usize = self.get_next_register()?;
                 match operand_2 {
-                    0 => return false,
+                    0 => {
+                        return Err(
+                            self.fault(|pc, instruction| VmError::DivideByZero { pc, instruction })
+                        )
+                    }
                     _ => {
-                        self.registers[register] = operand_1 / operand_2;
-                        self.div_remainder = (operand_1 % operand_2) as u32;
+                        let (result, flags): (i32, Flags) = Flags::div(operand_1, operand_2);
+                        self.registers[register] = result;
+                        self.flags = flags;
+                        self.div_remainder = operand_1.wrapping_rem(operand_2) as u32;
                     }
                 }
             }
             Opcode::JMP => {
-                self.program_counter = self.registers[self.get_next_8_bits() as usize] as usize;
+                let target: usize = self.registers[self.get_next_register()?] as usize;
+                self.skip_next_16_bits()?;
+                self.program_counter = target;
             }
+            // relative jumps are taken from the start of the next instruction
             Opcode::JMPF => {
-                self.program_counter += self.registers[self.get_next_8_bits() as usize] as usize;
+                let offset: usize = self.registers[self.get_next_register()?] as usize;
+                self.skip_next_16_bits()?;
+                self.program_counter = self.program_counter.wrapping_add(offset);
             }
             Opcode::JMPB => {
-                self.program_counter -= self.registers[self.get_next_8_bits() as usize] as usize;
-            }
-            Opcode::JEQ => {
-                if let true = self.eq_flag {
-                    self.program_counter = self.registers[self.get_next_8_bits() as usize] as usize;
-                }
+                let offset: usize = self.registers[self.get_next_register()?] as usize;
+                self.skip_next_16_bits()?;
+                self.program_counter = self.program_counter.wrapping_sub(offset);
             }
-            Opcode::JNEQ => {
-                if let false = self.eq_flag {
-                    self.program_counter = self.registers[self.get_next_8_bits() as usize] as usize;
-                }
-            }
-            Opcode::EQ => {
-                let operand_1: i32 = self.registers[self.get_next_8_bits() as usize];
-                let operand_2: i32 = self.registers[self.get_next_8_bits() as usize];
-                self.eq_flag = operand_1 == operand_2;
-                self.skip_next_8_bits();
-            }
-            Opcode::NEQ => {
-                let operand_1: i32 = self.registers[self.get_next_8_bits() as usize];
-                let operand_2: i32 = self.registers[self.get_next_8_bits() as usize];
-                self.eq_flag = operand_1 != operand_2;
-                self.skip_next_8_bits();
-            }
-            Opcode::GT => {
-                let operand_1: i32 = self.registers[self.get_next_8_bits() as usize];
-                let operand_2: i32 = self.registers[self.get_next_8_bits() as usize];
-                self.eq_flag = operand_1 > operand_2;
-                self.skip_next_8_bits();
-            }
-            Opcode::GEQ => {
-                let operand_1: i32 = self.registers[self.get_next_8_bits() as usize];
-                let operand_2: i32 = self.registers[self.get_next_8_bits() as usize];
-                self.eq_flag = operand_1 >= operand_2;
-                self.skip_next_8_bits();
-            }
-            Opcode::LE => {
-                let operand_1: i32 = self.registers[self.get_next_8_bits() as usize];
-                let operand_2: i32 = self.registers[self.get_next_8_bits() as usize];
-                self.eq_flag = operand_1 < operand_2;
-                self.skip_next_8_bits();
-            }
-            Opcode::LEQ => {
-                let operand_1: i32 = self.registers[self.get_next_8_bits() as usize];
-                let operand_2: i32 = self.registers[self.get_next_8_bits() as usize];
-                self.eq_flag = operand_1 <= operand_2;
-                self.skip_next_8_bits();
+            Opcode::JEQ | Opcode::JZ => self.jump_if(Condition::Zero)?,
+            Opcode::JNEQ | Opcode::JNZ => self.jump_if(Condition::NotZero)?,
+            Opcode::JLT => self.jump_if(Condition::Less)?,
+            Opcode::JGT => self.jump_if(Condition::Greater)?,
+            Opcode::JLE => self.jump_if(Condition::LessOrEqual)?,
+            Opcode::JGE => self.jump_if(Condition::GreaterOrEqual)?,
+            Opcode::JC => self.jump_on_flag(self.flags.carry)?,
+            Opcode::JNC => self.jump_on_flag(!self.flags.carry)?,
+            Opcode::JO => self.jump_on_flag(self.flags.overflow)?,
+            Opcode::JNO => self.jump_on_flag(!self.flags.overflow)?,
+            Opcode::BZ => self.branch_if(Condition::Zero)?,
+            Opcode::BNZ => self.branch_if(Condition::NotZero)?,
+            Opcode::BLT => self.branch_if(Condition::Less)?,
+            Opcode::BGT => self.branch_if(Condition::Greater)?,
+            Opcode::BLE => self.branch_if(Condition::LessOrEqual)?,
+            Opcode::BGE => self.branch_if(Condition::GreaterOrEqual)?,
+            Opcode::CMOVZ => self.move_if(Condition::Zero)?,
+            Opcode::CMOVNZ => self.move_if(Condition::NotZero)?,
+            Opcode::CMOVLT => self.move_if(Condition::Less)?,
+            Opcode::CMOVGT => self.move_if(Condition::Greater)?,
+            Opcode::CMOVLE => self.move_if(Condition::LessOrEqual)?,
+            Opcode::CMOVGE => self.move_if(Condition::GreaterOrEqual)?,
+            Opcode::CMP => {
+                let operand_1: i32 = self.registers[self.get_next_register()?];
+                let operand_2: i32 = self.registers[self.get_next_register()?];
+                self.skip_next_8_bits()?;
+                self.flags = Flags::compare(operand_1, operand_2);
             }
+            Opcode::EQ => self.relation(|a, b| a == b)?,
+            Opcode::NEQ => self.relation(|a, b| a != b)?,
+            Opcode::GT => self.relation(|a, b| a > b)?,
+            Opcode::GEQ => self.relation(|a, b| a >= b)?,
+            Opcode::LE => self.relation(|a, b| a < b)?,
+            Opcode::LEQ => self.relation(|a, b| a <= b)?,
             Opcode::INC => {
-                self.registers[self.get_next_8_bits() as usize] += 1;
-                self.skip_next_16_bits();
+                let register: usize = self.get_next_register()?;
+                self.skip_next_16_bits()?;
+                (self.registers[register], self.flags) = Flags::add(self.registers[register], 1);
             }
             Opcode::DEC => {
-                self.registers[self.get_next_8_bits() as usize] -= 1;
-                self.skip_next_16_bits();
+                let register: usize = self.get_next_register()?;
+                self.skip_next_16_bits()?;
+                (self.registers[register], self.flags) = Flags::sub(self.registers[register], 1);
             }
             Opcode::ALOC => {
-                let value: usize = self.registers[self.get_next_8_bits() as usize] as usize;
+                let value: usize = self.registers[self.get_next_register()?] as usize;
+                self.skip_next_16_bits()?;
                 self.heap.resize(value, 0);
             }
-            Opcode::HLT => return false,
-            Opcode::NOP => return false,
-            _ => return false,
+            Opcode::RSHT => {
+                // arithmetic shift, the shift amount is taken modulo 32
+                let operand_1: i32 = self.registers[self.get_next_register()?];
+                let operand_2: i32 = self.registers[self.get_next_register()?];
+                let register: usize = self.get_next_register()?;
+                self.registers[register] = operand_1.wrapping_shr(operand_2 as u32);
+            }
+            Opcode::LFST => {
+                let operand_1: i32 = self.registers[self.get_next_register()?];
+                let operand_2: i32 = self.registers[self.get_next_register()?];
+                let register: usize = self.get_next_register()?;
+                self.registers[register] = operand_1.wrapping_shl(operand_2 as u32);
+            }
+            Opcode::RROR => {
+                let operand_1: i32 = self.registers[self.get_next_register()?];
+                let operand_2: i32 = self.registers[self.get_next_register()?];
+                let register: usize = self.get_next_register()?;
+                self.registers[register] = (operand_1 as u32).rotate_right(operand_2 as u32) as i32;
+            }
+            Opcode::LROR => {
+                let operand_1: i32 = self.registers[self.get_next_register()?];
+                let operand_2: i32 = self.registers[self.get_next_register()?];
+                let register: usize = self.get_next_register()?;
+                self.registers[register] = (operand_1 as u32).rotate_left(operand_2 as u32) as i32;
+            }
+            Opcode::RSHTI => {
+                let register: usize = self.get_next_register()?;
+                let amount: u32 = self.get_next_16_bits()? as u32;
+                self.registers[register] = self.registers[register].wrapping_shr(amount);
+            }
+            Opcode::LFSTI => {
+                let register: usize = self.get_next_register()?;
+                let amount: u32 = self.get_next_16_bits()? as u32;
+                self.registers[register] = self.registers[register].wrapping_shl(amount);
+            }
+            Opcode::RRORI => {
+                let register: usize = self.get_next_register()?;
+                let amount: u32 = self.get_next_16_bits()? as u32;
+                self.registers[register] =
+                    (self.registers[register] as u32).rotate_right(amount) as i32;
+            }
+            Opcode::LRORI => {
+                let register: usize = self.get_next_register()?;
+                let amount: u32 = self.get_next_16_bits()? as u32;
+                self.registers[register] =
+                    (self.registers[register] as u32).rotate_left(amount) as i32;
+            }
+            Opcode::AND => {
+                let operand_1: i32 = self.registers[self.get_next_register()?];
+                let operand_2: i32 = self.registers[self.get_next_register()?];
+                let register: usize = self.get_next_register()?;
+                self.registers[register] = operand_1 & operand_2;
+            }
+            Opcode::OR => {
+                let operand_1: i32 = self.registers[self.get_next_register()?];
+                let operand_2: i32 = self.registers[self.get_next_register()?];
+                let register: usize = self.get_next_register()?;
+                self.registers[register] = operand_1 | operand_2;
+            }
+            Opcode::XOR => {
+                let operand_1: i32 = self.registers[self.get_next_register()?];
+                let operand_2: i32 = self.registers[self.get_next_register()?];
+                let register: usize = self.get_next_register()?;
+                self.registers[register] = operand_1 ^ operand_2;
+            }
+            Opcode::NOT => {
+                let operand: i32 = self.registers[self.get_next_register()?];
+                let register: usize = self.get_next_register()?;
+                self.registers[register] = !operand;
+                self.skip_next_8_bits()?;
+            }
+            Opcode::LOADDB => {
+                let register: usize = self.get_next_register()?;
+                let address: usize = self.registers[self.get_next_register()?] as usize;
+                self.skip_next_8_bits()?;
+                let data: &[u8] = self.read_ro_data(address, 1)?;
+                self.registers[register] = data[0] as i32;
+            }
+            Opcode::LOADDW => {
+                let register: usize = self.get_next_register()?;
+                let address: usize = self.registers[self.get_next_register()?] as usize;
+                self.skip_next_8_bits()?;
+                let data: &[u8] = self.read_ro_data(address, 4)?;
+                self.registers[register] = i32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+            }
+            Opcode::LOADDF => {
+                let register: usize = self.get_next_float_register()?;
+                let address: usize = self.get_next_16_bits()? as usize;
+                let data: &[u8] = self.read_ro_data(address, 8)?;
+                self.float_registers[register] =
+                    f64::from_be_bytes(data.try_into().unwrap_or_default());
+            }
+            Opcode::ADDF => self.float_arithmetic(|a, b| a + b)?,
+            Opcode::SUBF => self.float_arithmetic(|a, b| a - b)?,
+            Opcode::MULF => self.float_arithmetic(|a, b| a * b)?,
+            // division by zero gives an infinity or NaN, as in IEEE 754
+            Opcode::DIVF => self.float_arithmetic(|a, b| a / b)?,
+            Opcode::EQF => self.float_relation(|a, b| a == b)?,
+            Opcode::NEQF => self.float_relation(|a, b| a != b)?,
+            Opcode::GTF => self.float_relation(|a, b| a > b)?,
+            Opcode::GEQF => self.float_relation(|a, b| a >= b)?,
+            Opcode::LEF => self.float_relation(|a, b| a < b)?,
+            Opcode::LEQF => self.float_relation(|a, b| a <= b)?,
+            Opcode::ITOF => {
+                let register: usize = self.get_next_float_register()?;
+                let value: i32 = self.registers[self.get_next_register()?];
+                self.skip_next_8_bits()?;
+                self.float_registers[register] = value as f64;
+            }
+            Opcode::FTOI => {
+                let register: usize = self.get_next_register()?;
+                let value: f64 = self.float_registers[self.get_next_float_register()?];
+                self.skip_next_8_bits()?;
+                self.registers[register] = value as i32;
+            }
+            Opcode::PUSH => {
+                let value: i32 = self.registers[self.get_next_register()?];
+                self.skip_next_16_bits()?;
+                self.push(value)?;
+            }
+            Opcode::POP => {
+                let register: usize = self.get_next_register()?;
+                self.skip_next_16_bits()?;
+                self.registers[register] = self.pop()?;
+            }
+            Opcode::CALL => {
+                let target: usize = self.registers[self.get_next_register()?] as usize;
+                self.skip_next_16_bits()?;
+                // the return address is the instruction following the CALL
+                self.push(self.program_counter as i32)?;
+                self.program_counter = target;
+            }
+            Opcode::RET => {
+                self.skip_next_8_bits()?;
+                self.skip_next_16_bits()?;
+                self.program_counter = self.pop()? as usize;
+            }
+            Opcode::LOADB => {
+                let register: usize = self.get_next_register()?;
+                let address: usize = self.get_next_heap_address()?;
+                let byte: u8 = self.heap_range(address, 1)?[0];
+                self.registers[register] = byte as i32;
+            }
+            Opcode::LOADW => {
+                let register: usize = self.get_next_register()?;
+                let address: usize = self.get_next_heap_address()?;
+                let data: &mut [u8] = self.heap_range(address, 4)?;
+                self.registers[register] = i32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+            }
+            Opcode::STOREB => {
+                let address: usize = self.get_next_heap_address()?;
+                let value: i32 = self.registers[self.get_next_register()?];
+                self.heap_range(address, 1)?[0] = value as u8;
+            }
+            Opcode::STOREW => {
+                let address: usize = self.get_next_heap_address()?;
+                let value: i32 = self.registers[self.get_next_register()?];
+                self.heap_range(address, 4)?
+                    .copy_from_slice(&value.to_be_bytes());
+            }
+            Opcode::SYSCALL => {
+                let number: u16 = self.get_next_16_bits()?;
+                self.skip_next_8_bits()?;
+                self.syscall(number)?;
+            }
+            Opcode::HLT => {
+                self.skip_next_8_bits()?;
+                self.skip_next_16_bits()?;
+                return Ok(Some(ExitReason::Halted));
+            }
+            _ => {
+                return Err(self.fault(|pc, instruction| VmError::InvalidOpcode { pc, instruction }))
+            }
         }
-        // TODO program_counter % 16 == 0 because opcodes are every 4 bytes
-        // so pc must be a multiple of four to land on an opcode
+        // every instruction is INSTRUCTION_WIDTH bytes wide, unused operand bytes are zero padded
         // bytecode[0] 01 00 00 00 02 00 00 00 03 00 00 00
-        //                    ^^ second opcode is at bytecode[4] (then bytecode[8] ...)
-        true
+        //                         ^^ second opcode is at bytecode[4] (then bytecode[8] ...)
+        Ok(None)
     }
 
-    fn get_instruction_from_bytecode(&mut self) -> Opcode {
-        let instruction = Opcode::from(self.bytecode[self.program_counter]);
-        self.program_counter += 1;
-        instruction
+    fn trace_instruction(&self) {
+        let end: usize = usize::min(
+            self.program_counter + INSTRUCTION_WIDTH,
+            self.bytecode.len(),
+        );
+        let instruction: &[u8] = &self.bytecode[self.program_counter..end];
+        let bytes: Vec<String> = instruction
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        eprintln!(
+            "[TRACE] {:04X}: {:<11} {}",
+            self.program_counter,
+            bytes.join(" "),
+            disassemble_instruction(instruction).unwrap_or_else(|| "???".into())
+        );
     }
 
-    fn get_next_8_bits(&mut self) -> u8 {
-        let result = self.bytecode[self.program_counter];
-        self.program_counter += 1;
-        result
+    /// `op $a $b $dst` instructions setting the flags, `checked` ones fault
+    /// on signed overflow and leave the destination register unchanged
+    fn arithmetic(
+        &mut self,
+        operation: fn(i32, i32) -> (i32, Flags),
+        checked: bool,
+    ) -> Result<(), VmError> {
+        let operand_1: i32 = self.registers[self.get_next_register()?];
+        let operand_2: i32 = self.registers[self.get_next_register()?];
+        let register: usize = self.get_next_register()?;
+        let (result, flags): (i32, Flags) = operation(operand_1, operand_2);
+        if checked && flags.overflow {
+            return Err(self.fault(|pc, instruction| VmError::Overflow { pc, instruction }));
+        }
+        self.registers[register] = result;
+        self.flags = flags;
+        Ok(())
+    }
+
+    /// `op %fa %fb %fdst` float instructions
+    fn float_arithmetic(&mut self, operation: fn(f64, f64) -> f64) -> Result<(), VmError> {
+        let operand_1: f64 = self.float_registers[self.get_next_float_register()?];
+        let operand_2: f64 = self.float_registers[self.get_next_float_register()?];
+        let register: usize = self.get_next_float_register()?;
+        self.float_registers[register] = operation(operand_1, operand_2);
+        Ok(())
+    }
+
+    /// `op $a $b` relation tests, the zero flag is set when the relation holds
+    fn relation(&mut self, relation: fn(i32, i32) -> bool) -> Result<(), VmError> {
+        let operand_1: i32 = self.registers[self.get_next_register()?];
+        let operand_2: i32 = self.registers[self.get_next_register()?];
+        self.skip_next_8_bits()?;
+        self.flags = Flags::test(relation(operand_1, operand_2));
+        Ok(())
+    }
+
+    /// `op %fa %fb` float relation tests, the zero flag is set when the relation holds
+    fn float_relation(&mut self, relation: fn(f64, f64) -> bool) -> Result<(), VmError> {
+        let operand_1: f64 = self.float_registers[self.get_next_float_register()?];
+        let operand_2: f64 = self.float_registers[self.get_next_float_register()?];
+        self.skip_next_8_bits()?;
+        self.flags = Flags::test(relation(operand_1, operand_2));
+        Ok(())
+    }
+
+    fn jump_if(&mut self, condition: Condition) -> Result<(), VmError> {
+        self.jump_on_flag(self.flags.holds(condition))
+    }
+
+    /// jump to the address in the register operand if `condition` holds
+    fn jump_on_flag(&mut self, condition: bool) -> Result<(), VmError> {
+        let target: usize = self.registers[self.get_next_register()?] as usize;
+        self.skip_next_16_bits()?;
+        if condition {
+            self.program_counter = target;
+        }
+        Ok(())
+    }
+
+    /// move the signed 16-bit offset operand away from the next instruction if `condition` holds
+    fn branch_if(&mut self, condition: Condition) -> Result<(), VmError> {
+        let offset: i16 = self.get_next_16_bits()? as i16;
+        self.skip_next_8_bits()?;
+        if self.flags.holds(condition) {
+            self.program_counter = self.program_counter.wrapping_add_signed(offset as isize);
+        }
+        Ok(())
+    }
+
+    /// `CMOVcc $dst $src` copies $src into $dst if `condition` holds
+    fn move_if(&mut self, condition: Condition) -> Result<(), VmError> {
+        let register: usize = self.get_next_register()?;
+        let value: i32 = self.registers[self.get_next_register()?];
+        self.skip_next_8_bits()?;
+        if self.flags.holds(condition) {
+            self.registers[register] = value;
+        }
+        Ok(())
+    }
+
+    fn syscall(&mut self, number: u16) -> Result<(), VmError> {
+        let syscall: &mut Syscall = match self.syscalls.get_mut(&number) {
+            Some(syscall) => syscall,
+            None => {
+                return Err(self.fault(|pc, instruction| VmError::UnknownSyscall {
+                    pc,
+                    instruction,
+                    number,
+                }))
+            }
+        };
+        let mut context: SyscallContext = SyscallContext {
+            registers: &mut self.registers,
+            heap: &mut self.heap,
+            ro_data: &self.ro_data,
+        };
+        syscall(&mut context).map_err(|message| {
+            self.fault(|pc, instruction| VmError::SyscallFailed {
+                pc,
+                instruction,
+                number,
+                message,
+            })
+        })
+    }
+
+    fn push(&mut self, value: i32) -> Result<(), VmError> {
+        let end: usize = self.stack_pointer + 4;
+        if end > self.stack.len() {
+            return Err(self.fault(|pc, instruction| VmError::StackOverflow { pc, instruction }));
+        }
+        self.stack[self.stack_pointer..end].copy_from_slice(&value.to_be_bytes());
+        self.stack_pointer = end;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<i32, VmError> {
+        if self.stack_pointer < 4 {
+            return Err(self.fault(|pc, instruction| VmError::StackUnderflow { pc, instruction }));
+        }
+        self.stack_pointer -= 4;
+        let bytes: &[u8] = &self.stack[self.stack_pointer..self.stack_pointer + 4];
+        Ok(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// build a fault for the instruction being executed
+    fn fault(&self, error: impl FnOnce(usize, Vec<u8>) -> VmError) -> VmError {
+        let end: usize = usize::min(
+            self.instruction_start + INSTRUCTION_WIDTH,
+            self.bytecode.len(),
+        );
+        error(
+            self.instruction_start,
+            self.bytecode[self.instruction_start..end].to_vec(),
+        )
+    }
+
+    fn read_ro_data(&self, address: usize, len: usize) -> Result<&[u8], VmError> {
+        match address.checked_add(len) {
+            Some(end) if end <= self.ro_data.len() => Ok(&self.ro_data[address..end]),
+            _ => Err(self.fault(|pc, instruction| VmError::DataOutOfBounds {
+                pc,
+                instruction,
+                address,
+            })),
+        }
+    }
+
+    /// read a `[ $base + #offset ]` operand and return the heap address it points to
+    fn get_next_heap_address(&mut self) -> Result<usize, VmError> {
+        let base: i32 = self.registers[self.get_next_register()?];
+        let offset: u8 = self.get_next_8_bits()?;
+        Ok((base as usize).wrapping_add(offset as usize))
     }
 
-    fn get_next_16_bits(&mut self) -> u16 {
-        let result: u16 = ((self.bytecode[self.program_counter] as u16) << 8)
-            | self.bytecode[self.program_counter + 1] as u16;
-        self.program_counter += 2;
-        result
+    fn heap_range(&mut self, address: usize, len: usize) -> Result<&mut [u8], VmError> {
+        match address.checked_add(len) {
+            Some(end) if end <= self.heap.len() => Ok(&mut self.heap[address..end]),
+            _ => Err(self.fault(|pc, instruction| VmError::HeapOutOfBounds {
+                pc,
+                instruction,
+                address,
+            })),
+        }
+    }
+
+    fn get_instruction_from_bytecode(&mut self) -> Result<Opcode, VmError> {
+        let instruction = Opcode::from(self.get_next_8_bits()?);
+        Ok(instruction)
     }
 
-    fn skip_next_8_bits(&mut self) {
+    fn get_next_8_bits(&mut self) -> Result<u8, VmError> {
+        let result = match self.bytecode.get(self.program_counter) {
+            Some(byte) => *byte,
+            None => {
+                return Err(
+                    self.fault(|pc, instruction| VmError::TruncatedInstruction { pc, instruction })
+                )
+            }
+        };
         self.program_counter += 1;
+        Ok(result)
     }
 
-    fn skip_next_16_bits(&mut self) {
-        self.program_counter += 2;
+    fn get_next_16_bits(&mut self) -> Result<u16, VmError> {
+        let result: u16 = ((self.get_next_8_bits()? as u16) << 8) | self.get_next_8_bits()? as u16;
+        Ok(result)
+    }
+
+    /// read a register index and check it names one of the 32 registers
+    fn get_next_register(&mut self) -> Result<usize, VmError> {
+        let register: u8 = self.get_next_8_bits()?;
+        if register as usize >= self.registers.len() {
+            return Err(self.fault(|pc, instruction| VmError::InvalidRegister {
+                pc,
+                instruction,
+                register,
+            }));
+        }
+        Ok(register as usize)
+    }
+
+    /// read a float register index and check it names one of the 32 float registers
+    fn get_next_float_register(&mut self) -> Result<usize, VmError> {
+        let register: u8 = self.get_next_8_bits()?;
+        if register as usize >= self.float_registers.len() {
+            return Err(self.fault(|pc, instruction| VmError::InvalidFloatRegister {
+                pc,
+                instruction,
+                register,
+            }));
+        }
+        Ok(register as usize)
+    }
+
+    fn skip_next_8_bits(&mut self) -> Result<(), VmError> {
+        self.get_next_8_bits()?;
+        Ok(())
+    }
+
+    fn skip_next_16_bits(&mut self) -> Result<(), VmError> {
+        self.get_next_16_bits()?;
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::assembler::assemble;
 
     #[test]
     fn load() {
         let mut vm = VM::new();
         vm.bytecode = vec![1, 1, 1, 244];
-        vm.run();
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
         assert_eq!(vm.registers[1], 500)
     }
 
+    #[test]
+    fn load_clears_the_previous_run() {
+        let mut vm = VM::new();
+        vm.load_program(&assemble("LOAD $3 #7\nPUSH $3\nLOAD $4 #16\nALOC $4\nCMP $3 $3").unwrap())
+            .unwrap();
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
+        vm.float_registers[2] = 1.5;
+        vm.load_program(&assemble("HLT").unwrap()).unwrap();
+        assert_eq!(vm.registers, [0; REGISTER_COUNT]);
+        assert_eq!(vm.float_registers, [0.0; REGISTER_COUNT]);
+        assert_eq!(vm.stack_pointer, 0);
+        assert!(vm.heap.is_empty());
+        assert_eq!(vm.flags, Flags::default());
+        assert_eq!(vm.program_counter, 0);
+    }
+
     #[test]
     fn add() {
         let mut vm = VM::new();
         vm.registers[0] = 6;
         vm.registers[1] = 6;
         vm.bytecode = vec![2, 0, 1, 2];
-        vm.run();
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
         assert_eq!(vm.registers[2], 12)
     }
 
@@ -205,7 +766,7 @@ mod test {
         vm.registers[0] = 5;
         vm.registers[1] = 4;
         vm.bytecode = vec![3, 0, 1, 2];
-        vm.run();
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
         assert_eq!(vm.registers[2], 1)
     }
 
@@ -215,7 +776,7 @@ mod test {
         vm.registers[0] = 5;
         vm.registers[1] = 2;
         vm.bytecode = vec![4, 0, 1, 2];
-        vm.run();
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
         assert_eq!(vm.registers[2], 10)
     }
 
@@ -225,7 +786,7 @@ mod test {
         vm.registers[0] = 10;
         vm.registers[1] = 3;
         vm.bytecode = vec![5, 0, 1, 2];
-        vm.run();
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
         assert_eq!(vm.div_remainder, 1);
         assert_eq!(vm.registers[2], 3)
     }
@@ -236,8 +797,14 @@ mod test {
         vm.registers[0] = 2;
         vm.registers[1] = 0;
         vm.bytecode = vec![5, 0, 1, 2];
-        vm.run();
-        assert_eq!(vm.eq_flag, false)
+        assert_eq!(
+            vm.run(),
+            Err(VmError::DivideByZero {
+                pc: 0,
+                instruction: vec![5, 0, 1, 2]
+            })
+        );
+        assert!(!vm.flags.zero)
     }
 
     #[test]
@@ -245,26 +812,547 @@ mod test {
         let mut vm = VM::new();
         vm.registers[0] = 5;
         vm.bytecode = vec![14, 0, 0, 0];
-        vm.run();
+        assert_eq!(
+            vm.run(),
+            Err(VmError::PcOutOfBounds {
+                pc: 5,
+                bytecode_len: 4
+            })
+        );
         assert_eq!(vm.program_counter, 5)
     }
 
     #[test]
     fn jmpf() {
         let mut vm = VM::new();
-        vm.registers[0] = 5;
-        vm.bytecode = vec![15, 0, 0, 0];
-        vm.run();
-        assert_eq!(vm.program_counter, 7)
+        vm.registers[0] = 4;
+        vm.bytecode = vec![15, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 244];
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(vm.registers[1], 500)
+    }
+
+    #[test]
+    fn jneq_not_taken() {
+        let mut vm = VM::new();
+        vm.registers[0] = 0;
+        vm.flags.zero = true;
+        vm.bytecode = vec![13, 0, 0, 0, 1, 1, 1, 244];
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(vm.registers[1], 500)
     }
 
     #[test]
     fn jeq() {
         let mut vm = VM::new();
         vm.registers[0] = 5;
-        vm.eq_flag = true;
+        vm.flags.zero = true;
         vm.bytecode = vec![12, 0, 0, 0];
-        vm.run();
+        assert_eq!(
+            vm.run(),
+            Err(VmError::PcOutOfBounds {
+                pc: 5,
+                bytecode_len: 4
+            })
+        );
         assert_eq!(vm.program_counter, 5)
     }
+
+    #[test]
+    fn step() {
+        let mut vm = VM::new();
+        vm.bytecode = vec![1, 1, 1, 244, 5, 1, 2, 3, 0, 0, 0, 0];
+        assert_eq!(
+            vm.step(),
+            Step {
+                pc: 0,
+                instruction: vec![1, 1, 1, 244],
+                outcome: StepOutcome::Running
+            }
+        );
+        assert_eq!((vm.program_counter, vm.registers[1]), (4, 500));
+        assert!(matches!(vm.step().outcome, StepOutcome::Faulted(_)));
+        assert_eq!(vm.program_counter, 4);
+        vm.registers[2] = 5;
+        assert_eq!(vm.step().outcome, StepOutcome::Running);
+        assert_eq!(vm.step().outcome, StepOutcome::Halted(ExitReason::Halted));
+        let end: Step = vm.step();
+        assert_eq!((end.pc, end.instruction), (12, vec![]));
+        assert_eq!(end.outcome, StepOutcome::Halted(ExitReason::EndOfProgram))
+    }
+
+    #[test]
+    fn run_for() {
+        let mut vm = VM::new();
+        vm.bytecode = vec![17, 1, 0, 0, 14, 0, 0, 0];
+        assert_eq!(vm.run_for(10), RunOutcome::Preempted);
+        assert_eq!(vm.registers[1], 5);
+        vm.bytecode = vec![1, 1, 1, 244];
+        vm.program_counter = 0;
+        assert_eq!(vm.run_for(10), RunOutcome::Halted(ExitReason::EndOfProgram));
+        vm.program_counter = 0;
+        assert_eq!(vm.run_for(0), RunOutcome::Preempted);
+        vm.bytecode = vec![5, 1, 2, 3];
+        assert!(matches!(vm.run_for(1), RunOutcome::Faulted(_)))
+    }
+
+    #[test]
+    fn run_until() {
+        let mut vm = VM::new();
+        vm.bytecode = vec![17, 1, 0, 0, 14, 0, 0, 0];
+        assert_eq!(
+            vm.run_until(|vm| vm.registers[1] == 3),
+            RunOutcome::Preempted
+        );
+        assert_eq!((vm.registers[1], vm.program_counter), (3, 4))
+    }
+
+    #[test]
+    fn hlt() {
+        let mut vm = VM::new();
+        vm.bytecode = vec![0, 0, 0, 0, 1, 1, 1, 244];
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!(vm.registers[1], 0)
+    }
+
+    #[test]
+    fn invalid_opcode() {
+        let mut vm = VM::new();
+        vm.bytecode = vec![1, 1, 1, 244, 200, 0, 0, 0];
+        assert_eq!(
+            vm.run(),
+            Err(VmError::InvalidOpcode {
+                pc: 4,
+                instruction: vec![200, 0, 0, 0]
+            })
+        )
+    }
+
+    #[test]
+    fn invalid_register() {
+        let mut vm = VM::new();
+        vm.bytecode = vec![1, 32, 1, 244];
+        assert_eq!(
+            vm.run(),
+            Err(VmError::InvalidRegister {
+                pc: 0,
+                instruction: vec![1, 32, 1, 244],
+                register: 32
+            })
+        )
+    }
+
+    #[test]
+    fn truncated_instruction() {
+        let mut vm = VM::new();
+        vm.bytecode = vec![1, 1, 1];
+        assert_eq!(
+            vm.run(),
+            Err(VmError::TruncatedInstruction {
+                pc: 0,
+                instruction: vec![1, 1, 1]
+            })
+        )
+    }
+
+    #[test]
+    fn jmpb_before_start() {
+        let mut vm = VM::new();
+        vm.registers[0] = 8;
+        vm.bytecode = vec![16, 0, 0, 0];
+        assert!(matches!(vm.run(), Err(VmError::PcOutOfBounds { .. })))
+    }
+
+    #[test]
+    fn shifts() {
+        let mut vm = VM::new();
+        vm.registers[0] = -16;
+        vm.registers[1] = 2;
+        vm.bytecode = vec![20, 0, 1, 2, 21, 0, 1, 3];
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(vm.registers[2], -4);
+        assert_eq!(vm.registers[3], -64)
+    }
+
+    #[test]
+    fn rotates() {
+        let mut vm = VM::new();
+        vm.registers[0] = 0x0000_00F1;
+        vm.registers[1] = 4;
+        vm.bytecode = vec![22, 0, 1, 2, 23, 0, 1, 3];
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(vm.registers[2] as u32, 0x1000_000F);
+        assert_eq!(vm.registers[3], 0x0000_0F10)
+    }
+
+    #[test]
+    fn shift_by_immediate() {
+        let mut vm = VM::new();
+        vm.registers[0] = 1;
+        vm.registers[1] = 0x10;
+        vm.registers[2] = 1;
+        vm.registers[3] = 1;
+        vm.bytecode = vec![
+            29, 0, 0, 33, // shift amount is taken modulo 32
+            28, 1, 0, 4, 30, 2, 0, 1, 31, 3, 0, 31,
+        ];
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(vm.registers[0], 2);
+        assert_eq!(vm.registers[1], 1);
+        assert_eq!(vm.registers[2], i32::MIN);
+        assert_eq!(vm.registers[3], i32::MIN)
+    }
+
+    #[test]
+    fn bitwise() {
+        let mut vm = VM::new();
+        vm.registers[0] = 0b1100;
+        vm.registers[1] = 0b1010;
+        vm.bytecode = vec![24, 0, 1, 2, 25, 0, 1, 3, 26, 0, 1, 4, 27, 0, 5, 0];
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(vm.registers[2], 0b1000);
+        assert_eq!(vm.registers[3], 0b1110);
+        assert_eq!(vm.registers[4], 0b0110);
+        assert_eq!(vm.registers[5], !0b1100)
+    }
+
+    #[test]
+    fn load_ro_data() {
+        let mut vm = VM::new();
+        vm.ro_data = vec![7, 0, 0, 1, 2];
+        vm.registers[0] = 1;
+        vm.bytecode = vec![32, 1, 0, 0, 33, 2, 0, 0];
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(vm.registers[1], 0);
+        assert_eq!(vm.registers[2], 258)
+    }
+
+    #[test]
+    fn ro_data_out_of_bounds() {
+        let mut vm = VM::new();
+        vm.ro_data = vec![7, 0, 0];
+        vm.bytecode = vec![33, 1, 0, 0];
+        assert_eq!(
+            vm.run(),
+            Err(VmError::DataOutOfBounds {
+                pc: 0,
+                instruction: vec![33, 1, 0, 0],
+                address: 0
+            })
+        )
+    }
+
+    #[test]
+    fn push_pop() {
+        let mut vm = VM::new();
+        vm.registers[0] = -7;
+        vm.registers[1] = 3;
+        vm.bytecode = vec![34, 0, 0, 0, 34, 1, 0, 0, 35, 2, 0, 0, 35, 3, 0, 0];
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(vm.registers[2], 3);
+        assert_eq!(vm.registers[3], -7);
+        assert_eq!(vm.stack_pointer, 0)
+    }
+
+    #[test]
+    fn call_ret() {
+        let mut vm = VM::new();
+        vm.registers[31] = 12;
+        vm.bytecode = vec![
+            36, 31, 0, 0, // CALL $31
+            1, 1, 0, 2, // LOAD $1 #2
+            0, 0, 0, 0, // HLT
+            1, 0, 0, 1, // LOAD $0 #1
+            37, 0, 0, 0, // RET
+        ];
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!((vm.registers[0], vm.registers[1]), (1, 2));
+        assert_eq!(vm.stack_pointer, 0)
+    }
+
+    #[test]
+    fn stack_overflow() {
+        let mut vm = VM::new();
+        vm.bytecode = vec![34, 0, 0, 0, 14, 1, 0, 0];
+        assert_eq!(
+            vm.run(),
+            Err(VmError::StackOverflow {
+                pc: 0,
+                instruction: vec![34, 0, 0, 0]
+            })
+        );
+        assert_eq!(vm.stack_pointer, 1024)
+    }
+
+    #[test]
+    fn stack_underflow() {
+        let mut vm = VM::new();
+        vm.bytecode = vec![37, 0, 0, 0];
+        assert_eq!(
+            vm.run(),
+            Err(VmError::StackUnderflow {
+                pc: 0,
+                instruction: vec![37, 0, 0, 0]
+            })
+        )
+    }
+
+    #[test]
+    fn heap_load_store() {
+        let mut vm = VM::new();
+        vm.registers[0] = 16;
+        vm.registers[1] = 4;
+        vm.registers[2] = -2;
+        vm.bytecode = vec![
+            19, 0, 0, 0, // ALOC $0
+            41, 1, 8, 2, // STOREW [ $1 + #8 ] $2
+            40, 1, 0, 0, // STOREB [ $1 ] $0
+            39, 3, 1, 8, // LOADW $3 [ $1 + #8 ]
+            38, 4, 1, 11, // LOADB $4 [ $1 + #11 ]
+        ];
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(vm.heap[4], 16);
+        assert_eq!(vm.heap[12..16], [255, 255, 255, 254]);
+        assert_eq!((vm.registers[3], vm.registers[4]), (-2, 254))
+    }
+
+    #[test]
+    fn heap_out_of_bounds() {
+        let mut vm = VM::new();
+        vm.heap = vec![0; 8];
+        vm.registers[1] = 6;
+        vm.bytecode = vec![39, 0, 1, 0];
+        assert_eq!(
+            vm.run(),
+            Err(VmError::HeapOutOfBounds {
+                pc: 0,
+                instruction: vec![39, 0, 1, 0],
+                address: 6
+            })
+        );
+        vm.registers[1] = -1;
+        vm.program_counter = 0;
+        vm.bytecode = vec![40, 1, 0, 0];
+        assert!(matches!(vm.run(), Err(VmError::HeapOutOfBounds { .. })))
+    }
+
+    #[test]
+    fn host_syscall() {
+        let mut vm = VM::new();
+        vm.register_syscall(100, |context| {
+            context.registers[0] = context.heap.len() as i32;
+            Ok(())
+        });
+        vm.register_syscall(101, |_| Err("not today".into()));
+        vm.heap = vec![0; 12];
+        vm.bytecode = vec![42, 0, 100, 0, 42, 0, 101, 0];
+        assert_eq!(
+            vm.run(),
+            Err(VmError::SyscallFailed {
+                pc: 4,
+                instruction: vec![42, 0, 101, 0],
+                number: 101,
+                message: "not today".into()
+            })
+        );
+        assert_eq!(vm.registers[0], 12);
+        vm.bytecode = vec![42, 1, 0, 0];
+        vm.program_counter = 0;
+        assert_eq!(
+            vm.run().unwrap_err().to_string(),
+            "unknown system call #256 at 0x0000 [2A 01 00 00]"
+        )
+    }
+
+    #[test]
+    fn wrapping_arithmetic() {
+        let mut vm = VM::new();
+        vm.registers[0] = i32::MAX;
+        vm.registers[1] = 1;
+        vm.registers[2] = i32::MIN;
+        vm.registers[3] = -1;
+        vm.bytecode = vec![
+            2, 0, 1, 4, // ADD $0 $1 $4
+            3, 2, 1, 5, // SUB $2 $1 $5
+            4, 0, 0, 6, // MUL $0 $0 $6
+            5, 2, 3, 7, // DIV $2 $3 $7
+            17, 3, 0, 0, // INC $3
+        ];
+        vm.step();
+        assert_eq!(vm.registers[4], i32::MIN);
+        assert_eq!(
+            vm.flags,
+            Flags {
+                zero: false,
+                negative: true,
+                carry: false,
+                overflow: true
+            }
+        );
+        vm.step();
+        assert_eq!(vm.registers[5], i32::MAX);
+        assert!(vm.flags.overflow && !vm.flags.carry && !vm.flags.negative);
+        vm.step();
+        assert_eq!(vm.registers[6], 1);
+        assert!(vm.flags.overflow && vm.flags.carry);
+        vm.step();
+        assert_eq!((vm.registers[7], vm.div_remainder), (i32::MIN, 0));
+        assert!(vm.flags.overflow);
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(vm.registers[3], 0);
+        assert_eq!(
+            vm.flags,
+            Flags {
+                zero: true,
+                negative: false,
+                carry: true,
+                overflow: false
+            }
+        )
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        let mut vm = VM::new();
+        vm.registers[0] = i32::MAX;
+        vm.registers[1] = 1;
+        vm.bytecode = vec![44, 0, 1, 2, 43, 0, 1, 3];
+        assert_eq!(
+            vm.run(),
+            Err(VmError::Overflow {
+                pc: 4,
+                instruction: vec![43, 0, 1, 3]
+            })
+        );
+        assert_eq!((vm.registers[2], vm.registers[3]), (i32::MAX - 1, 0));
+        assert!(!vm.flags.overflow)
+    }
+
+    #[test]
+    fn jump_on_flags() {
+        let mut vm = VM::new();
+        vm.registers[0] = 1;
+        vm.registers[1] = 16;
+        vm.bytecode = vec![
+            18, 0, 0, 0, // DEC $0
+            47, 1, 0, 0, // JNZ $1
+            3, 0, 1, 2, // SUB $0 $1 $2
+            48, 1, 0, 0, // JC $1
+            0, 0, 0, 0, // HLT
+        ];
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!((vm.program_counter, vm.registers[2]), (20, -16));
+        vm.registers[0] = -1;
+        vm.registers[2] = 0;
+        vm.program_counter = 0;
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        assert_eq!((vm.registers[0], vm.registers[2]), (-2, 0))
+    }
+
+    #[test]
+    fn float_instructions() {
+        let mut vm = VM::new();
+        vm.float_registers[0] = 1.5;
+        vm.float_registers[1] = 0.0;
+        vm.registers[0] = -3;
+        vm.bytecode = vec![
+            63, 2, 0, 0, // ITOF %f2 $0
+            55, 0, 2, 3, // MULF %f0 %f2 %f3
+            56, 0, 1, 4, // DIVF %f0 %f1 %f4
+            54, 4, 4, 5, // SUBF %f4 %f4 %f5
+            64, 1, 3, 0, // FTOI $1 %f3
+            64, 2, 4, 0, // FTOI $2 %f4
+            64, 3, 5, 0, // FTOI $3 %f5
+            61, 3, 1, 0, // LEF %f3 %f1
+        ];
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(vm.float_registers[3], -4.5);
+        assert_eq!(vm.float_registers[4], f64::INFINITY);
+        assert!(vm.float_registers[5].is_nan());
+        assert_eq!(
+            (vm.registers[1], vm.registers[2], vm.registers[3]),
+            (-4, i32::MAX, 0)
+        );
+        assert!(vm.flags.zero);
+        vm.bytecode = vec![58, 5, 5, 0, 57, 5, 5, 0];
+        vm.program_counter = 0;
+        vm.step();
+        assert!(vm.flags.zero);
+        vm.step();
+        assert!(!vm.flags.zero)
+    }
+
+    #[test]
+    fn invalid_float_register() {
+        let mut vm = VM::new();
+        vm.bytecode = vec![53, 0, 32, 1];
+        assert_eq!(
+            vm.run().unwrap_err().to_string(),
+            "invalid float register index %f32 at 0x0000 [35 00 20 01]"
+        )
+    }
+
+    #[test]
+    fn condition_codes() {
+        let mut vm = VM::new();
+        vm.registers[0] = i32::MIN;
+        vm.registers[1] = 1;
+        vm.registers[2] = 7;
+        vm.bytecode = vec![
+            65, 0, 1, 0, // CMP $0 $1, a - b overflows but $0 is still the lesser
+            78, 3, 2, 0, // CMOVLT $3 $2
+            79, 4, 2, 0, // CMOVGT $4 $2
+            65, 1, 1, 0, // CMP $1 $1
+            74, 0, 4, 0, // BLE #4
+            0, 0, 0, 0, // HLT
+            73, 255, 248, 0, // BGT #-8
+            76, 5, 2, 0, // CMOVZ $5 $2
+        ];
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(
+            (vm.registers[3], vm.registers[4], vm.registers[5]),
+            (7, 0, 7)
+        );
+        for (operand_1, operand_2, expected) in [
+            (3, 5, [false, true, true, false, true, false]),
+            (5, 5, [true, false, false, false, true, true]),
+            (-1, i32::MAX, [false, true, true, false, true, false]),
+            (i32::MAX, -1, [false, true, false, true, false, true]),
+        ] {
+            let flags: Flags = Flags::compare(operand_1, operand_2);
+            let holds: Vec<bool> = [
+                Condition::Zero,
+                Condition::NotZero,
+                Condition::Less,
+                Condition::Greater,
+                Condition::LessOrEqual,
+                Condition::GreaterOrEqual,
+            ]
+            .into_iter()
+            .map(|condition| flags.holds(condition))
+            .collect();
+            assert_eq!(holds, expected, "CMP {} {}", operand_1, operand_2)
+        }
+    }
+
+    #[test]
+    fn conditional_jumps() {
+        let mut vm = VM::new();
+        vm.registers[0] = -2;
+        vm.registers[1] = 3;
+        vm.registers[2] = 28;
+        vm.registers[3] = 16;
+        vm.registers[4] = 32;
+        vm.bytecode = vec![
+            65, 0, 1, 0, // CMP $0 $1
+            69, 2, 0, 0, // JGE $2
+            66, 3, 0, 0, // JLT $3
+            0, 0, 0, 0, // HLT
+            65, 1, 0, 0, // CMP $1 $0
+            68, 2, 0, 0, // JLE $2
+            67, 4, 0, 0, // JGT $4
+            0, 0, 0, 0, // HLT
+        ];
+        assert_eq!(vm.run(), Ok(ExitReason::EndOfProgram));
+        assert_eq!(vm.program_counter, 32)
+    }
 }
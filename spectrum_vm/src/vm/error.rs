@@ -0,0 +1,164 @@
+use std::fmt;
+
+/// why the vm stopped executing without faulting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExitReason {
+    /// an HLT instruction was executed
+    Halted,
+    /// the program counter reached the end of the bytecode
+    EndOfProgram,
+}
+
+/// runtime faults, every variant carries the pc of the faulting instruction
+/// and the instruction bytes that could be read from there
+#[derive(Debug, Clone, PartialEq)]
+pub enum VmError {
+    DivideByZero {
+        pc: usize,
+        instruction: Vec<u8>,
+    },
+    /// a checked instruction overflowed
+    Overflow {
+        pc: usize,
+        instruction: Vec<u8>,
+    },
+    InvalidOpcode {
+        pc: usize,
+        instruction: Vec<u8>,
+    },
+    InvalidRegister {
+        pc: usize,
+        instruction: Vec<u8>,
+        register: u8,
+    },
+    InvalidFloatRegister {
+        pc: usize,
+        instruction: Vec<u8>,
+        register: u8,
+    },
+    TruncatedInstruction {
+        pc: usize,
+        instruction: Vec<u8>,
+    },
+    PcOutOfBounds {
+        pc: usize,
+        bytecode_len: usize,
+    },
+    DataOutOfBounds {
+        pc: usize,
+        instruction: Vec<u8>,
+        address: usize,
+    },
+    HeapOutOfBounds {
+        pc: usize,
+        instruction: Vec<u8>,
+        address: usize,
+    },
+    StackOverflow {
+        pc: usize,
+        instruction: Vec<u8>,
+    },
+    StackUnderflow {
+        pc: usize,
+        instruction: Vec<u8>,
+    },
+    UnknownSyscall {
+        pc: usize,
+        instruction: Vec<u8>,
+        number: u16,
+    },
+    SyscallFailed {
+        pc: usize,
+        instruction: Vec<u8>,
+        number: u16,
+        message: String,
+    },
+}
+
+impl VmError {
+    pub fn pc(&self) -> usize {
+        match self {
+            VmError::DivideByZero { pc, .. }
+            | VmError::Overflow { pc, .. }
+            | VmError::InvalidOpcode { pc, .. }
+            | VmError::InvalidRegister { pc, .. }
+            | VmError::InvalidFloatRegister { pc, .. }
+            | VmError::TruncatedInstruction { pc, .. }
+            | VmError::PcOutOfBounds { pc, .. }
+            | VmError::DataOutOfBounds { pc, .. }
+            | VmError::HeapOutOfBounds { pc, .. }
+            | VmError::StackOverflow { pc, .. }
+            | VmError::StackUnderflow { pc, .. }
+            | VmError::UnknownSyscall { pc, .. }
+            | VmError::SyscallFailed { pc, .. } => *pc,
+        }
+    }
+
+    pub fn instruction(&self) -> &[u8] {
+        match self {
+            VmError::DivideByZero { instruction, .. }
+            | VmError::Overflow { instruction, .. }
+            | VmError::InvalidOpcode { instruction, .. }
+            | VmError::InvalidRegister { instruction, .. }
+            | VmError::InvalidFloatRegister { instruction, .. }
+            | VmError::TruncatedInstruction { instruction, .. }
+            | VmError::DataOutOfBounds { instruction, .. }
+            | VmError::HeapOutOfBounds { instruction, .. }
+            | VmError::StackOverflow { instruction, .. }
+            | VmError::StackUnderflow { instruction, .. }
+            | VmError::UnknownSyscall { instruction, .. }
+            | VmError::SyscallFailed { instruction, .. } => instruction,
+            VmError::PcOutOfBounds { .. } => &[],
+        }
+    }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::DivideByZero { .. } => write!(f, "division by zero")?,
+            VmError::Overflow { .. } => write!(f, "integer overflow")?,
+            VmError::InvalidOpcode { instruction, .. } => {
+                write!(f, "invalid opcode {:#04X}", instruction[0])?
+            }
+            VmError::InvalidRegister { register, .. } => {
+                write!(f, "invalid register index ${}", register)?
+            }
+            VmError::InvalidFloatRegister { register, .. } => {
+                write!(f, "invalid float register index %f{}", register)?
+            }
+            VmError::TruncatedInstruction { .. } => {
+                write!(f, "instruction truncated by the end of the bytecode")?
+            }
+            VmError::PcOutOfBounds { bytecode_len, .. } => write!(
+                f,
+                "program counter out of bounds (bytecode is {} bytes long)",
+                bytecode_len
+            )?,
+            VmError::DataOutOfBounds { address, .. } => {
+                write!(f, "read-only data address {:#06X} out of bounds", address)?
+            }
+            VmError::HeapOutOfBounds { address, .. } => {
+                write!(f, "heap address {:#06X} out of bounds", address)?
+            }
+            VmError::StackOverflow { .. } => write!(f, "stack overflow")?,
+            VmError::StackUnderflow { .. } => write!(f, "stack underflow")?,
+            VmError::UnknownSyscall { number, .. } => write!(f, "unknown system call #{}", number)?,
+            VmError::SyscallFailed {
+                number, message, ..
+            } => write!(f, "system call #{} failed : {}", number, message)?,
+        }
+        write!(f, " at {:#06X}", self.pc())?;
+        if !self.instruction().is_empty() {
+            let bytes: Vec<String> = self
+                .instruction()
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+            write!(f, " [{}]", bytes.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for VmError {}
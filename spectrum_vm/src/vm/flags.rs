@@ -0,0 +1,87 @@
+/// condition flags set by CMP and the arithmetic instructions, results always wrap around
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Flags {
+    /// the result is zero, for CMP the operands are equal
+    /// the relation tests EQ ... LEQ and EQF ... LEQF set it when their relation holds
+    pub zero: bool,
+    /// the result is negative
+    pub negative: bool,
+    /// the unsigned operation carried out of 32 bits, for a subtraction the
+    /// second operand was greater than the first as unsigned integers (a borrow)
+    pub carry: bool,
+    /// the signed result does not fit in 32 bits
+    pub overflow: bool,
+}
+
+/// conditions read by the JZ ... JGE jumps, the BZ ... BGE branches and
+/// the CMOVZ ... CMOVGE moves, `Less` and `Greater` compare as signed after `CMP $a $b`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    Zero,
+    NotZero,
+    Less,
+    Greater,
+    LessOrEqual,
+    GreaterOrEqual,
+}
+
+impl Flags {
+    /// flags of `CMP $a $b`, those of the subtraction a - b
+    pub fn compare(operand_1: i32, operand_2: i32) -> Flags {
+        Flags::sub(operand_1, operand_2).1
+    }
+
+    /// flags of a relation test, only zero is set, to whether the relation holds
+    pub fn test(holds: bool) -> Flags {
+        Flags {
+            zero: holds,
+            ..Flags::default()
+        }
+    }
+
+    pub fn holds(&self, condition: Condition) -> bool {
+        // a - b is negative when a < b unless the subtraction overflowed
+        let less: bool = self.negative != self.overflow;
+        match condition {
+            Condition::Zero => self.zero,
+            Condition::NotZero => !self.zero,
+            Condition::Less => less,
+            Condition::Greater => !less && !self.zero,
+            Condition::LessOrEqual => less || self.zero,
+            Condition::GreaterOrEqual => !less,
+        }
+    }
+
+    fn new(result: i32, carry: bool, overflow: bool) -> Self {
+        Self {
+            zero: result == 0,
+            negative: result < 0,
+            carry,
+            overflow,
+        }
+    }
+
+    pub fn add(operand_1: i32, operand_2: i32) -> (i32, Flags) {
+        let (result, overflow): (i32, bool) = operand_1.overflowing_add(operand_2);
+        let carry: bool = (operand_1 as u32).overflowing_add(operand_2 as u32).1;
+        (result, Flags::new(result, carry, overflow))
+    }
+
+    pub fn sub(operand_1: i32, operand_2: i32) -> (i32, Flags) {
+        let (result, overflow): (i32, bool) = operand_1.overflowing_sub(operand_2);
+        let carry: bool = (operand_1 as u32) < (operand_2 as u32);
+        (result, Flags::new(result, carry, overflow))
+    }
+
+    pub fn mul(operand_1: i32, operand_2: i32) -> (i32, Flags) {
+        let (result, overflow): (i32, bool) = operand_1.overflowing_mul(operand_2);
+        let carry: bool = (operand_1 as u32).overflowing_mul(operand_2 as u32).1;
+        (result, Flags::new(result, carry, overflow))
+    }
+
+    /// the divisor must not be zero, `i32::MIN / -1` overflows to `i32::MIN`
+    pub fn div(operand_1: i32, operand_2: i32) -> (i32, Flags) {
+        let (result, overflow): (i32, bool) = operand_1.overflowing_div(operand_2);
+        (result, Flags::new(result, false, overflow))
+    }
+}
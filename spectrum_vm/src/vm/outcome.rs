@@ -0,0 +1,30 @@
+use super::error::{ExitReason, VmError};
+
+/// state of the vm after executing one instruction
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepOutcome {
+    /// the instruction was executed and the program can continue
+    Running,
+    Halted(ExitReason),
+    /// the instruction faulted, the pc is left on it
+    Faulted(VmError),
+}
+
+/// an instruction executed by `VM::step`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// address of the instruction
+    pub pc: usize,
+    /// bytes read at `pc`, shorter than an instruction at the end of the bytecode
+    pub instruction: Vec<u8>,
+    pub outcome: StepOutcome,
+}
+
+/// how a bounded run ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunOutcome {
+    Halted(ExitReason),
+    Faulted(VmError),
+    /// the run was stopped before the program halted, by its instruction budget or predicate
+    Preempted,
+}
@@ -0,0 +1,241 @@
+use std::{
+    cell::RefCell,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
+
+use super::REGISTER_COUNT;
+
+/// print $0 as a decimal integer
+pub const PRINT_INT: u16 = 0;
+/// print $0 as a unicode character
+pub const PRINT_CHAR: u16 = 1;
+/// print the NUL-terminated read-only data string at address $0
+pub const PRINT_STRING: u16 = 2;
+/// print the $1 heap bytes starting at address $0
+pub const PRINT_HEAP: u16 = 3;
+/// read a line from the input and parse it into $0
+pub const READ_INT: u16 = 4;
+/// read a line into the heap at address $0, at most $1 bytes without the line break
+/// $0 is set to the number of bytes written, or -1 at the end of the input
+pub const READ_LINE: u16 = 5;
+
+/// the part of the vm a system call can read and change
+pub struct SyscallContext<'a> {
+    pub registers: &'a mut [i32; REGISTER_COUNT],
+    pub heap: &'a mut Vec<u8>,
+    pub ro_data: &'a [u8],
+}
+
+impl SyscallContext<'_> {
+    /// bytes of the NUL-terminated string at `address` in the read-only data
+    pub fn ro_string(&self, address: usize) -> Result<&[u8], String> {
+        let data: &[u8] = self
+            .ro_data
+            .get(address..)
+            .ok_or_else(|| format!("read-only data address {:#06X} out of bounds", address))?;
+        match data.iter().position(|byte| *byte == 0) {
+            Some(len) => Ok(&data[..len]),
+            None => Err(format!("string at {:#06X} is not NUL-terminated", address)),
+        }
+    }
+
+    pub fn heap_range(&mut self, address: usize, len: usize) -> Result<&mut [u8], String> {
+        match address.checked_add(len) {
+            Some(end) if end <= self.heap.len() => Ok(&mut self.heap[address..end]),
+            _ => Err(format!("heap address {:#06X} out of bounds", address)),
+        }
+    }
+}
+
+/// a host function run by `SYSCALL #n`, an error stops the vm with `VmError::SyscallFailed`
+pub type Syscall = Box<dyn FnMut(&mut SyscallContext) -> Result<(), String>>;
+
+/// appends the next input line to the string, like `BufRead::read_line`
+type ReadLine = Box<dyn FnMut(&mut String) -> io::Result<usize>>;
+
+/// streams the built-in system calls read lines from and print to
+pub struct Console {
+    read_line: ReadLine,
+    output: Box<dyn Write>,
+}
+
+impl Console {
+    pub fn new(mut input: impl BufRead + 'static, output: impl Write + 'static) -> Self {
+        Self {
+            read_line: Box::new(move |line| input.read_line(line)),
+            output: Box::new(output),
+        }
+    }
+
+    /// stdin is locked for each line only, so the repl can still read from it
+    pub fn stdio() -> Self {
+        Self {
+            read_line: Box::new(|line| io::stdin().read_line(line)),
+            output: Box::new(io::stdout()),
+        }
+    }
+
+    fn print(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.output
+            .write_all(bytes)
+            .and_then(|_| self.output.flush())
+            .map_err(|err| format!("couldn't write output : {}", err))
+    }
+
+    /// next input line without its line break, None at the end of the input
+    fn line(&mut self) -> Result<Option<String>, String> {
+        let mut line: String = String::new();
+        match (self.read_line)(&mut line) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(line.trim_end_matches(['\n', '\r']).into())),
+            Err(err) => Err(format!("couldn't read input : {}", err)),
+        }
+    }
+}
+
+/// the built-in system calls, printing to and reading from `console`
+pub fn builtins(console: Console) -> Vec<(u16, Syscall)> {
+    let shared: Rc<RefCell<Console>> = Rc::new(RefCell::new(console));
+    let mut syscalls: Vec<(u16, Syscall)> = Vec::new();
+
+    let console: Rc<RefCell<Console>> = Rc::clone(&shared);
+    syscalls.push((
+        PRINT_INT,
+        Box::new(move |context| {
+            let text: String = context.registers[0].to_string();
+            console.borrow_mut().print(text.as_bytes())
+        }),
+    ));
+    let console: Rc<RefCell<Console>> = Rc::clone(&shared);
+    syscalls.push((
+        PRINT_CHAR,
+        Box::new(move |context| {
+            let code: u32 = context.registers[0] as u32;
+            let c: char =
+                char::from_u32(code).ok_or_else(|| format!("{:#X} is not a character", code))?;
+            console.borrow_mut().print(c.to_string().as_bytes())
+        }),
+    ));
+    let console: Rc<RefCell<Console>> = Rc::clone(&shared);
+    syscalls.push((
+        PRINT_STRING,
+        Box::new(move |context| {
+            let string: &[u8] = context.ro_string(context.registers[0] as usize)?;
+            console.borrow_mut().print(string)
+        }),
+    ));
+    let console: Rc<RefCell<Console>> = Rc::clone(&shared);
+    syscalls.push((
+        PRINT_HEAP,
+        Box::new(move |context| {
+            let (address, len): (usize, usize) =
+                (context.registers[0] as usize, context.registers[1] as usize);
+            let bytes: &[u8] = context.heap_range(address, len)?;
+            console.borrow_mut().print(bytes)
+        }),
+    ));
+    let console: Rc<RefCell<Console>> = Rc::clone(&shared);
+    syscalls.push((
+        READ_INT,
+        Box::new(move |context| {
+            let line: String = console
+                .borrow_mut()
+                .line()?
+                .ok_or("expected an integer, reached the end of the input")?;
+            context.registers[0] = line
+                .trim()
+                .parse()
+                .map_err(|_| format!("expected an integer, read '{}'", line.trim()))?;
+            Ok(())
+        }),
+    ));
+    let console: Rc<RefCell<Console>> = Rc::clone(&shared);
+    syscalls.push((
+        READ_LINE,
+        Box::new(move |context| {
+            let line: String = match console.borrow_mut().line()? {
+                Some(line) => line,
+                None => {
+                    context.registers[0] = -1;
+                    return Ok(());
+                }
+            };
+            let address: usize = context.registers[0] as usize;
+            let len: usize = usize::min(line.len(), context.registers[1].max(0) as usize);
+            context
+                .heap_range(address, len)?
+                .copy_from_slice(&line.as_bytes()[..len]);
+            context.registers[0] = len as i32;
+            Ok(())
+        }),
+    ));
+    syscalls
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        assembler::assemble,
+        vm::{error::ExitReason, VM},
+    };
+
+    use super::*;
+
+    /// output shared with the test once the console is moved into the vm
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_with_input(source: &str, input: &'static str) -> (VM, String) {
+        let output: Output = Output::default();
+        let mut vm: VM = VM::new();
+        vm.set_console(Console::new(input.as_bytes(), output.clone()));
+        vm.load_program(&assemble(source).unwrap()).unwrap();
+        assert_eq!(vm.run(), Ok(ExitReason::Halted));
+        let text: String = String::from_utf8(output.0.take()).unwrap();
+        (vm, text)
+    }
+
+    #[test]
+    fn builtin_syscalls() {
+        let (vm, output) = run_with_input(
+            ".data\nprompt: .asciiz \"name? \"\n.code\n\
+             main: LA $0 @prompt\nSYSCALL #2\nLOAD $0 #16\nALOC $0\n\
+             LOAD $0 #4\nLOAD $1 #8\nSYSCALL #5\nLOAD $2 #0\nADD $0 $2 $1\nLOAD $0 #4\nSYSCALL #3\n\
+             LOAD $0 #'!'\nSYSCALL #1\nSYSCALL #4\nINC $0\nSYSCALL #0\n\
+             LOAD $0 #0\nSYSCALL #5\nHLT",
+            "spectrum vm\n41\n",
+        );
+        assert_eq!(output, "name? spectrum!42");
+        assert_eq!(vm.heap[4..12], *b"spectrum");
+        assert_eq!(vm.registers[0], -1)
+    }
+
+    #[test]
+    fn builtin_syscall_errors() {
+        let mut vm: VM = VM::new();
+        vm.set_console(Console::new("four\n".as_bytes(), Output::default()));
+        vm.load_program(&assemble("SYSCALL #4").unwrap()).unwrap();
+        assert!(vm
+            .run()
+            .unwrap_err()
+            .to_string()
+            .starts_with("system call #4 failed : expected an integer, read 'four'"));
+        vm.load_program(&assemble("LOAD $0 #3\nLOAD $1 #1\nSYSCALL #3").unwrap())
+            .unwrap();
+        assert!(matches!(
+            vm.run(),
+            Err(crate::vm::error::VmError::SyscallFailed { number: 3, .. })
+        ))
+    }
+}